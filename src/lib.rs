@@ -1,10 +1,17 @@
 // src/lib.rs
 
 pub mod source {
+    pub mod archive;
+    pub mod auth;
+    pub mod files;
     pub mod git;
+    pub mod provider;
+    pub mod url;
 }
 
 pub mod tools {
+    pub mod cli;
+    pub mod config;
     pub mod errors;
     pub mod fops;
     pub mod ui;
@@ -12,7 +19,14 @@ pub mod tools {
 
 pub mod trans_md {
     pub mod code_md;
+    #[cfg(feature = "parquet")]
+    pub mod columnar;
+    pub mod document;
+    pub mod languages;
+    pub mod linguist;
+    pub mod manifest;
     pub mod md_json;
+    pub mod render;
 }
 
 pub use source::git;
@@ -1,74 +1,194 @@
 // main.rs
 
-use goblin_scout::source::git;
-use goblin_scout::tools::{errors::CustomError, fops, ui::prompt_for_repo_details};
+use clap::Parser;
+use goblin_scout::source::provider::{open_source, Source};
+use goblin_scout::tools::cli::{Cli, Command, ConfigArgs};
+use goblin_scout::tools::config::{ConfigLayer, JsonlRecords, Mode, Settings, SettingsLoader};
+use goblin_scout::tools::ui::{prompt_for_repo_details, prompt_line};
+use goblin_scout::tools::{errors::CustomError, fops};
 use goblin_scout::trans_md::code_md as markdown_processor;
-use goblin_scout::trans_md::md_json::convert_md_to_json;
-use std::io;
-use std::path::{Path, PathBuf};
+use goblin_scout::trans_md::languages::{default_map, map_to_json};
+use goblin_scout::trans_md::linguist::{import_linguist_file, keep_categories, MapChanges};
+use goblin_scout::trans_md::md_json::{
+    convert_md_to_json, convert_md_to_jsonl, error_report_path, ConversionReport,
+};
+use std::path::Path;
+use std::process;
 
 fn main() {
     if let Err(e) = run() {
         eprintln!("Error: {}", e);
+        process::exit(1);
     }
 }
 
-fn determine_output_directory(path: &Path) -> Result<PathBuf, CustomError> {
-    path.parent()
-        .map(|p| p.to_path_buf())
-        .ok_or_else(|| CustomError::StrError("Invalid output path provided.".to_string()))
+fn run() -> Result<(), CustomError> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        None => run_interactive(&ConfigArgs::default()),
+        Some(Command::Interactive(args)) => run_interactive(&args),
+        Some(Command::Single(args)) => {
+            let loader = args.repo.settings_loader()?;
+            let (source, settings) = prepare_source(&args.repo.source, &loader)?;
+            run_mode(
+                Mode::Single,
+                source.as_ref(),
+                &settings,
+                args.output_file.as_deref(),
+            )
+        }
+        Some(Command::Multi(args)) => {
+            let (source, settings) = prepare_source(&args.source, &args.settings_loader()?)?;
+            run_mode(Mode::Multi, source.as_ref(), &settings, None)
+        }
+        Some(Command::Dataset(args)) => {
+            let loader = args.settings_loader()?;
+            let (source, settings) = prepare_source(&args.repo.source, &loader)?;
+            run_mode(Mode::Dataset, source.as_ref(), &settings, None)
+        }
+        Some(Command::Run(args)) => {
+            let loader = args.settings_loader()?;
+            let (source, settings) = prepare_source(&args.repo.source, &loader)?;
+            let mode = settings.mode.ok_or_else(|| {
+                CustomError::ConfigError(
+                    "No mode set, pass --mode or set `mode` in goblin.toml".to_string(),
+                )
+            })?;
+            run_mode(mode, source.as_ref(), &settings, None)
+        }
+        Some(Command::ToJson(args)) => match args.jsonl {
+            Some(records) => run_to_jsonl(&args.input, &args.output, records, args.lenient),
+            None => run_to_json(&args.input, &args.output, args.lenient),
+        },
+        Some(Command::ImportLanguages(args)) => {
+            run_import_languages(&args.input, args.output.as_deref())
+        }
+    }
 }
 
-fn run() -> Result<(), CustomError> {
-    let repo_details = prompt_for_repo_details()?;
-    let repo = git::git_repo_check(&repo_details)?;
+// Open the source, cloning or updating remotes, then re-resolve settings with its own goblin.toml.
+fn prepare_source(
+    spec: &str,
+    loader: &SettingsLoader,
+) -> Result<(Box<dyn Source>, Settings), CustomError> {
+    let source = open_source(spec, &loader.settings()?)?;
+    let settings = loader.settings_for_source(source.root())?;
+    Ok((source, settings))
+}
+
+fn run_interactive(config: &ConfigArgs) -> Result<(), CustomError> {
+    let loader = config.settings_loader(ConfigLayer::default())?;
+    let repo_details = prompt_for_repo_details(&loader.settings()?)?;
+    let (source, mut settings) = prepare_source(&repo_details.url, &loader)?;
 
     println!("Please select an option for markdown output:");
     println!("1: Generate a single markdown file.");
     println!("2: Generate individual markdown files.");
-    println!("3: Generate dataset markdown.");
+    let option = prompt_line("3: Generate dataset markdown.")?;
 
-    let mut option = String::new();
-    io::stdin().read_line(&mut option)?;
-    let option = option.trim();
+    let mode = match option.as_str() {
+        "1" => Mode::Single,
+        "2" => Mode::Multi,
+        "3" => {
+            let json_option = prompt_line("Would you like to create a JSON file? (y/n)")?;
+            settings.json = json_option.eq_ignore_ascii_case("y");
+            Mode::Dataset
+        }
+        _ => {
+            println!("Invalid option selected.");
+            return Err(CustomError::StrError(
+                "Invalid option provided.".to_string(),
+            ));
+        }
+    };
 
-    let output_directory = determine_output_directory(&repo_details.markdown_output)?;
+    run_mode(mode, source.as_ref(), &settings, None)
+}
 
-    match option {
-        "1" => {
-            let markdown_content = markdown_processor::code_md_single_markdown(&repo)?;
-            fops::fops_write(&repo_details.markdown_output, markdown_content)?;
+fn run_mode(
+    mode: Mode,
+    source: &dyn Source,
+    settings: &Settings,
+    output_file: Option<&Path>,
+) -> Result<(), CustomError> {
+    let output_directory = &settings.output_dir;
+
+    match mode {
+        Mode::Single => {
+            let markdown_output = output_file
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| output_directory.join(format!("{}.md", source.name())));
+            let markdown_content = markdown_processor::code_md_single_markdown(source, settings)?;
+            fops::fops_write(&markdown_output, markdown_content)?;
             println!("Single markdown file updated.");
         }
-        "2" => {
-            markdown_processor::code_md_multi_markdown(&repo, &output_directory)?;
+        Mode::Multi => {
+            markdown_processor::code_md_multi_markdown(source, output_directory, settings)?;
             println!("Individual markdown files generated.");
         }
-        "3" => {
-            markdown_processor::code_md_dataset_markdown(&repo, &output_directory)?;
+        Mode::Dataset => {
+            markdown_processor::code_md_dataset_markdown(source, output_directory, settings)?;
             println!("Dataset markdown generated.");
+        }
+    }
+    Ok(())
+}
 
-            println!("Would you like to create a JSON file? (y/n)");
-            let mut json_option = String::new();
-            io::stdin().read_line(&mut json_option)?;
+fn run_to_json(md_directory: &Path, json_path: &Path, lenient: bool) -> Result<(), CustomError> {
+    println!("Converting MD in directory: {:?}", md_directory);
+    let report = convert_md_to_json(md_directory, json_path, lenient)?;
+    println!("JSON file created at: {:?}", json_path);
+    print_skipped(&report, json_path);
+    Ok(())
+}
 
-            if json_option.trim().eq_ignore_ascii_case("y") {
-                let md_directory = output_directory.join("dataset").join(&repo_details.name);
-                println!("Converting MD in directory: {:?}", md_directory);
+fn run_to_jsonl(
+    md_directory: &Path,
+    jsonl_path: &Path,
+    records: JsonlRecords,
+    lenient: bool,
+) -> Result<(), CustomError> {
+    println!("Converting MD in directory: {:?}", md_directory);
+    let report = convert_md_to_jsonl(md_directory, jsonl_path, records, lenient)?;
+    println!("JSON Lines file created at: {:?}", jsonl_path);
+    print_skipped(&report, jsonl_path);
+    Ok(())
+}
 
-                let json_path = output_directory.join(format!("{}.json", &repo_details.name));
-                convert_md_to_json(&md_directory, &json_path)?;
-                println!("JSON file created at: {:?}", json_path);
-            }
+fn print_skipped(report: &ConversionReport, output_path: &Path) {
+    if !report.skipped.is_empty() {
+        for skipped in &report.skipped {
+            let line = skipped.line.map_or(String::new(), |l| format!(":{}", l));
+            eprintln!(
+                "Skipped {}{}: {}",
+                skipped.path.display(),
+                line,
+                skipped.error
+            );
         }
+        println!(
+            "{} files converted, {} skipped; see {:?}",
+            report.converted,
+            report.skipped.len(),
+            error_report_path(output_path)
+        );
+    }
+}
 
-        _ => {
-            println!("Invalid option selected.");
-            return Err(CustomError::StrError(
-                "Invalid option provided.".to_string(),
-            ));
+// Convert Linguist's languages.yml, report changes against the built-in map and optionally write it.
+fn run_import_languages(input: &Path, output: Option<&Path>) -> Result<(), CustomError> {
+    let current = default_map()?;
+    let mut imported = import_linguist_file(input)?;
+    keep_categories(&current, &mut imported);
+    print!("{}", MapChanges::between(&current, &imported));
+
+    match output {
+        Some(path) => {
+            fops::fops_write(path, map_to_json(&imported)?)?;
+            println!("Language map written to {}", path.display());
         }
+        None => println!("No --output given, nothing written."),
     }
-
     Ok(())
 }
@@ -0,0 +1,57 @@
+// source/archive.rs
+
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::tools::errors::CustomError;
+
+// Archive extensions accepted as sources, longest first so `.tar.gz` wins over `.gz`.
+pub const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tgz", ".tar", ".zip"];
+
+// Return the archive extension of a path, if it has a supported one.
+pub fn archive_extension(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .find(|ext| name.ends_with(*ext))
+        .copied()
+}
+
+// Extract a tarball or zip into a fresh `dest` directory and return the directory holding its
+// content. Release archives usually wrap everything in one top-level folder, which is skipped.
+pub fn extract_archive(archive: &Path, dest: &Path) -> Result<PathBuf, CustomError> {
+    if dest.exists() {
+        fs::remove_dir_all(dest)?;
+    }
+    fs::create_dir_all(dest)?;
+
+    let file = File::open(archive)?;
+    match archive_extension(archive) {
+        Some(".tar.gz") | Some(".tgz") => unpack_tar(GzDecoder::new(file), dest)?,
+        Some(".tar") => unpack_tar(file, dest)?,
+        Some(".zip") => zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(dest))
+            .map_err(|e| {
+                CustomError::StrError(format!("Failed to extract {}: {}", archive.display(), e))
+            })?,
+        _ => {
+            return Err(CustomError::StrError(format!(
+                "Unsupported archive format: {}",
+                archive.display()
+            )))
+        }
+    }
+
+    let entries = fs::read_dir(dest)?.collect::<Result<Vec<_>, _>>()?;
+    match entries.as_slice() {
+        [single] if single.file_type()?.is_dir() => Ok(single.path()),
+        _ => Ok(dest.to_path_buf()),
+    }
+}
+
+fn unpack_tar<R: Read>(reader: R, dest: &Path) -> Result<(), CustomError> {
+    tar::Archive::new(reader).unpack(dest)?;
+    Ok(())
+}
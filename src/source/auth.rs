@@ -0,0 +1,242 @@
+// source/auth.rs
+
+use git2::{
+    Config, Cred, CredentialType, Error as GitError, ErrorClass, ErrorCode, RemoteCallbacks,
+};
+use std::cell::RefCell;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::tools::errors::CustomError;
+
+// Environment variables checked for an HTTPS token when none is configured.
+pub const DEFAULT_TOKEN_ENVS: &[&str] = &["GOBLIN_GIT_TOKEN", "GITHUB_TOKEN", "GITLAB_TOKEN"];
+pub const DEFAULT_PASSPHRASE_ENV: &str = "GOBLIN_SSH_PASSPHRASE";
+// Returned to libgit2 once every credential method has been offered.
+const NO_CREDENTIALS_LEFT: &str = "no remaining credentials to try";
+
+/// Credentials available for cloning and fetching private repositories.
+#[derive(Debug, Clone, Default)]
+pub struct AuthSettings {
+    /// Username for SSH and HTTPS when the URL does not contain one.
+    pub username: Option<String>,
+    /// Private key used after the SSH agent. The public key is read from `<key>.pub` if present.
+    pub ssh_key: Option<PathBuf>,
+    /// Environment variable holding the private key passphrase.
+    pub ssh_passphrase_env: Option<String>,
+    /// Environment variable holding an HTTPS token, instead of `DEFAULT_TOKEN_ENVS`.
+    pub token_env: Option<String>,
+}
+
+/// Supplies credentials to libgit2 and remembers which methods were tried, so failures can
+/// explain what was attempted. Each method is offered once per remote operation.
+pub struct GitAuth<'a> {
+    settings: &'a AuthSettings,
+    tried: RefCell<Vec<String>>,
+}
+
+impl<'a> GitAuth<'a> {
+    pub fn new(settings: &'a AuthSettings) -> Self {
+        GitAuth {
+            settings,
+            tried: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Callbacks for one clone, connect or fetch. Starts a fresh record of tried methods.
+    pub fn callbacks(&self) -> RemoteCallbacks<'_> {
+        self.tried.borrow_mut().clear();
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username_from_url, allowed| {
+            self.credentials(url, username_from_url, allowed)
+        });
+        callbacks
+    }
+
+    fn credentials(
+        &self,
+        url: &str,
+        username_from_url: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, GitError> {
+        let username = username_from_url
+            .or(self.settings.username.as_deref())
+            .unwrap_or("git");
+
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if self.attempt("ssh-agent") {
+                return Cred::ssh_key_from_agent(username);
+            }
+            if let Some(key) = &self.settings.ssh_key {
+                let key = expand_home(key);
+                if self.attempt(&format!("ssh key {}", key.display())) {
+                    let public_key = key.with_extension("pub");
+                    let passphrase = self.passphrase();
+                    return Cred::ssh_key(
+                        username,
+                        public_key.exists().then_some(public_key.as_path()),
+                        &key,
+                        passphrase.as_deref(),
+                    );
+                }
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if let Some((var, token)) = self.token() {
+                if self.attempt(&format!("token from ${}", var)) {
+                    let username = username_from_url
+                        .or(self.settings.username.as_deref())
+                        .unwrap_or("x-access-token");
+                    return Cred::userpass_plaintext(username, &token);
+                }
+            }
+            if self.attempt("git credential helper") {
+                let config = Config::open_default()?;
+                return Cred::credential_helper(&config, url, username_from_url);
+            }
+        }
+
+        Err(GitError::from_str(NO_CREDENTIALS_LEFT))
+    }
+
+    // Record a method and return whether it has not been tried yet.
+    fn attempt(&self, method: &str) -> bool {
+        let mut tried = self.tried.borrow_mut();
+        if tried.iter().any(|m| m == method) {
+            return false;
+        }
+        tried.push(method.to_string());
+        true
+    }
+
+    fn passphrase(&self) -> Option<String> {
+        let var = self
+            .settings
+            .ssh_passphrase_env
+            .as_deref()
+            .unwrap_or(DEFAULT_PASSPHRASE_ENV);
+        env::var(var).ok()
+    }
+
+    fn token(&self) -> Option<(String, String)> {
+        let vars = match &self.settings.token_env {
+            Some(var) => vec![var.as_str()],
+            None => DEFAULT_TOKEN_ENVS.to_vec(),
+        };
+        vars.into_iter().find_map(|var| {
+            env::var(var)
+                .ok()
+                .filter(|token| !token.is_empty())
+                .map(|token| (var.to_string(), token))
+        })
+    }
+
+    // Turn authentication failures into an `AuthError` listing the methods that were tried.
+    // Other failures, such as an unreachable host, are passed through unchanged.
+    pub fn map_error(&self, url: &str, error: GitError) -> CustomError {
+        if !is_auth_failure(&error) {
+            return error.into();
+        }
+
+        let tried = self.tried.borrow();
+        let tried = if tried.is_empty() {
+            "no credentials were requested".to_string()
+        } else {
+            format!("tried {}", tried.join(", "))
+        };
+        CustomError::AuthError(format!("{} ({}): {}", url, tried, error.message()))
+    }
+}
+
+// Rejected credentials: libgit2's auth code, an HTTP 401 or 403, an SSH authentication failure,
+// or every credential method having been tried.
+fn is_auth_failure(error: &GitError) -> bool {
+    let message = error.message().to_lowercase();
+    match error.class() {
+        _ if error.code() == ErrorCode::Auth => true,
+        _ if message == NO_CREDENTIALS_LEFT => true,
+        ErrorClass::Http => ["401", "403"].iter().any(|status| message.contains(status)),
+        ErrorClass::Ssh => message.contains("authenticat"),
+        _ => false,
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::build::RepoBuilder;
+    use git2::FetchOptions;
+
+    fn is_auth_error(error: GitError) -> bool {
+        let settings = AuthSettings::default();
+        matches!(
+            GitAuth::new(&settings).map_error("url", error),
+            CustomError::AuthError(_)
+        )
+    }
+
+    #[test]
+    fn test_only_rejected_credentials_are_auth_errors() {
+        let error = |code, class, message| GitError::new(code, class, message);
+        assert!(is_auth_error(error(
+            ErrorCode::Auth,
+            ErrorClass::Http,
+            "too many redirects or authentication replays"
+        )));
+        assert!(is_auth_error(error(
+            ErrorCode::GenericError,
+            ErrorClass::Http,
+            "unexpected http status code: 403"
+        )));
+        assert!(is_auth_error(error(
+            ErrorCode::GenericError,
+            ErrorClass::Ssh,
+            "Failed to authenticate SSH session: Unable to open public key file"
+        )));
+        assert!(is_auth_error(GitError::from_str(NO_CREDENTIALS_LEFT)));
+        assert!(!is_auth_error(error(
+            ErrorCode::GenericError,
+            ErrorClass::Http,
+            "unexpected http status code: 404"
+        )));
+        assert!(!is_auth_error(error(
+            ErrorCode::GenericError,
+            ErrorClass::Ssh,
+            "failed to resolve address for example.invalid: Name or service not known"
+        )));
+    }
+
+    #[test]
+    fn test_unreachable_host_is_not_an_auth_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = AuthSettings::default();
+        let auth = GitAuth::new(&settings);
+        let url = "http://127.0.0.1:1/repo.git";
+
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(auth.callbacks());
+        let error = RepoBuilder::new()
+            .fetch_options(fetch_options)
+            .clone(url, &dir.path().join("repo"))
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            auth.map_error(url, error),
+            CustomError::GitError(_)
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+// source/files.rs
+
+use git2::{ObjectType, Oid, Repository};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tools::config::Settings;
+use crate::tools::errors::CustomError;
+use crate::tools::fops::fops_skip;
+
+// Extra ignore file read from the source alongside `.gitignore`.
+pub const GOBLIN_IGNORE_FILE: &str = ".goblinignore";
+
+pub(crate) const FILEMODE_BLOB: i32 = 0o100644;
+pub(crate) const FILEMODE_BLOB_EXECUTABLE: i32 = 0o100755;
+
+// Where the content of a `RepoFile` is read from.
+pub(crate) enum FileLocation<'a> {
+    Disk(PathBuf),
+    Blob(&'a Repository),
+}
+
+/// A file to document, listed from a directory on disk or a git tree.
+pub struct RepoFile<'a> {
+    /// Path relative to the source root.
+    pub path: PathBuf,
+    /// Git blob ID of the content. Files listed from a directory get it in `select_files`.
+    pub oid: Oid,
+    /// Git file mode, e.g. `0o100644` or `0o100755`.
+    pub mode: i32,
+    /// Content size in bytes.
+    pub size: u64,
+    pub(crate) location: FileLocation<'a>,
+}
+
+impl RepoFile<'_> {
+    pub fn read_bytes(&self) -> Result<Vec<u8>, CustomError> {
+        match &self.location {
+            FileLocation::Disk(path) => Ok(fs::read(path)?),
+            FileLocation::Blob(repo) => Ok(repo.find_blob(self.oid)?.content().to_vec()),
+        }
+    }
+
+    // Where the content lives on disk, for files that are not read from a git tree.
+    pub fn disk_path(&self) -> Option<&Path> {
+        match &self.location {
+            FileLocation::Disk(path) => Some(path),
+            FileLocation::Blob(_) => None,
+        }
+    }
+
+    pub fn read_to_string(&self) -> Result<String, CustomError> {
+        String::from_utf8(self.read_bytes()?).map_err(|_| {
+            CustomError::StrError(format!("{} is not valid UTF-8", self.path.display()))
+        })
+    }
+
+    pub fn file_name(&self) -> Cow<'_, str> {
+        self.path.file_name().unwrap_or_default().to_string_lossy()
+    }
+
+    pub fn extension(&self) -> &str {
+        self.path
+            .extension()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default()
+    }
+
+    // Check the first 1024 bytes for NUL, the same heuristic as `fops_skip`.
+    fn is_binary(&self) -> Result<bool, CustomError> {
+        match &self.location {
+            FileLocation::Disk(path) => fops_skip(path),
+            FileLocation::Blob(repo) => {
+                let blob = repo.find_blob(self.oid)?;
+                let content = blob.content();
+                Ok(content[..content.len().min(1024)].contains(&0x00))
+            }
+        }
+    }
+}
+
+// Blob ID of a file on disk, or `None` when it is binary or cannot be read.
+fn text_blob_id(path: &Path) -> Result<Option<Oid>, CustomError> {
+    if fops_skip(path).unwrap_or(true) {
+        return Ok(None);
+    }
+    Ok(Some(Oid::hash_file(ObjectType::Blob, path)?))
+}
+
+// Keep the files that pass the include/exclude rules and are not binary. Files on disk are
+// checked and hashed on the worker pool once the path rules have left them in; blobs borrow
+// the repository, which cannot be shared between threads, so they are checked here.
+pub fn select_files<'a>(
+    files: Vec<RepoFile<'a>>,
+    settings: &Settings,
+) -> Result<Vec<RepoFile<'a>>, CustomError> {
+    let files: Vec<RepoFile<'a>> = files
+        .into_iter()
+        .filter(|file| settings.filter.allows(&file.path))
+        .collect();
+    let disk_paths: Vec<Option<&Path>> = files.iter().map(RepoFile::disk_path).collect();
+    let disk_oids = settings.worker_pool()?.install(|| {
+        disk_paths
+            .par_iter()
+            .map(|path| path.map_or(Ok(None), text_blob_id))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    Ok(files
+        .into_iter()
+        .zip(disk_oids)
+        .filter_map(|(mut file, disk_oid)| match file.location {
+            FileLocation::Disk(_) => disk_oid.map(|oid| {
+                file.oid = oid;
+                file
+            }),
+            FileLocation::Blob(_) => (!file.is_binary().unwrap_or(true)).then_some(file),
+        })
+        .collect())
+}
+
+// List files under a directory. Any `.git` directory is never visited, and `.gitignore`,
+// `.git/info/exclude`, the global excludes file and any `.goblinignore` files are honored.
+// Content is not read here, so blob IDs are left for `select_files` to fill in.
+pub fn directory_files(root: &Path) -> Result<Vec<RepoFile<'static>>, CustomError> {
+    let walker = WalkBuilder::new(root)
+        .hidden(false)
+        .require_git(false)
+        .add_custom_ignore_filename(GOBLIN_IGNORE_FILE)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(|e| CustomError::StrError(e.to_string()))?;
+        let path = entry.path();
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let metadata = fs::metadata(path)?;
+        let mode = if is_executable(&metadata) {
+            FILEMODE_BLOB_EXECUTABLE
+        } else {
+            FILEMODE_BLOB
+        };
+
+        files.push(RepoFile {
+            path: path.strip_prefix(root).unwrap_or(path).to_path_buf(),
+            oid: Oid::zero(),
+            mode,
+            size: metadata.len(),
+            location: FileLocation::Disk(path.to_path_buf()),
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_directory_files_honor_ignore_files() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        for (path, content) in [
+            (".git/HEAD", "ref: refs/heads/main\n"),
+            (".gitignore", "target/\n"),
+            (".goblinignore", "*.log\n"),
+            (".github/ci.yml", "on: push\n"),
+            ("notes.log", "debug\n"),
+            ("src/a.rs", "fn a() {}\n"),
+            ("target/debug/out.rs", "fn out() {}\n"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let files = select_files(directory_files(root).unwrap(), &Settings::default()).unwrap();
+        let paths: Vec<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [".github/ci.yml", ".gitignore", ".goblinignore", "src/a.rs"].map(Path::new)
+        );
+        assert_eq!(
+            files[3].oid,
+            Oid::hash_object(ObjectType::Blob, b"fn a() {}\n").unwrap()
+        );
+    }
+}
@@ -1,14 +1,21 @@
 // source/git.rs
 
-use git2::{AutotagOption, FetchOptions, ObjectType, Repository};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{
+    AutotagOption, Direction, FetchOptions, ObjectType, Oid, Remote, Repository, ResetType, Tree,
+    TreeWalkMode, TreeWalkResult,
+};
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
 };
-use walkdir::{DirEntry, WalkDir};
 
+use crate::source::auth::GitAuth;
+use crate::source::files::{
+    directory_files, FileLocation, RepoFile, FILEMODE_BLOB, FILEMODE_BLOB_EXECUTABLE,
+};
+use crate::tools::config::{FileSource, Settings, UpdateStrategy};
 use crate::tools::errors::CustomError;
-use crate::tools::fops::fops_skip;
 
 pub struct RepositoryDetails {
     pub url: String,
@@ -17,42 +24,277 @@ pub struct RepositoryDetails {
     pub markdown_output: PathBuf,
 }
 
-pub fn git_repo_check(details: &RepositoryDetails) -> Result<Repository, CustomError> {
+impl RepositoryDetails {
+    // Derive the repository name, clone location and single markdown output from a repo URL.
+    pub fn new(url: &str, settings: &Settings) -> Self {
+        let name = repo_name(url);
+
+        RepositoryDetails {
+            url: url.to_string(),
+            local_path: settings.cache_dir.join(&name),
+            markdown_output: settings.output_dir.join(format!("{}.md", &name)),
+            name,
+        }
+    }
+}
+
+pub fn git_repo_check(
+    details: &RepositoryDetails,
+    settings: &Settings,
+) -> Result<Repository, CustomError> {
+    let auth = GitAuth::new(&settings.auth);
     if details.local_path.exists() {
-        git_repo_update(&details.local_path)
+        git_repo_update(&details.local_path, settings.update, &auth)
     } else {
-        git_repo_clone(&details.url, &details.local_path)
+        git_repo_clone(&details.url, &details.local_path, &auth)
     }
 }
 
-pub fn git_repo_update(local_repo_path: &PathBuf) -> Result<Repository, CustomError> {
+pub fn git_repo_update(
+    local_repo_path: &Path,
+    strategy: UpdateStrategy,
+    auth: &GitAuth,
+) -> Result<Repository, CustomError> {
     println!("Existing repo found. Pulling new data...");
-    let repo = Repository::open(&local_repo_path)?;
+    let repo = Repository::open(local_repo_path)?;
 
     // Attempt fetching updates only if the repository was successfully opened.
-    fetch_updates_from_remote(&repo)?;
+    fetch_updates_from_remote(&repo, strategy, auth)?;
 
     Ok(repo)
 }
 
-fn fetch_updates_from_remote(repo: &Repository) -> Result<(), CustomError> {
+// Fetch every branch and tag from the remote and move the local checkout to its default branch.
+fn fetch_updates_from_remote(
+    repo: &Repository,
+    strategy: UpdateStrategy,
+    auth: &GitAuth,
+) -> Result<(), CustomError> {
     let mut remote = repo.find_remote("origin")?;
+    let remote_url = remote.url().unwrap_or("origin").to_string();
+    let branch = remote_default_branch(repo, &mut remote, auth)
+        .map_err(|e| auth_error(auth, &remote_url, e))?;
 
     let mut fetch_options = FetchOptions::new();
     fetch_options.download_tags(AutotagOption::All);
-    remote.fetch(&["master"], Some(&mut fetch_options), None)?;
+    fetch_options.remote_callbacks(auth.callbacks());
+    // Other branches and tags are fetched too, so `rev` and the latest release see them.
+    let refspecs = [
+        "+refs/heads/*:refs/remotes/origin/*",
+        "+refs/tags/*:refs/tags/*",
+    ];
+    remote
+        .fetch(&refspecs, Some(&mut fetch_options), None)
+        .map_err(|e| auth.map_error(&remote_url, e))?;
+
+    let remote_commit = repo
+        .find_reference(&format!("refs/remotes/origin/{}", branch))?
+        .peel_to_commit()?;
+    let local_ref_name = format!("refs/heads/{}", branch);
+    let local_commit = match repo.find_reference(&local_ref_name) {
+        Ok(reference) => Some(reference.peel_to_commit()?.id()),
+        Err(_) => None,
+    };
+
+    let new = remote_commit.id();
+    if local_commit == Some(new) && repo.head()?.name() == Some(local_ref_name.as_str()) {
+        println!("Already up to date on {} at {}.", branch, short_id(new));
+        return Ok(());
+    }
 
+    if let (Some(old), UpdateStrategy::FastForward) = (local_commit, strategy) {
+        // Local commits that origin does not have yet are kept, the branch is only checked out.
+        if repo.graph_descendant_of(old, new)? {
+            repo.checkout_tree(
+                repo.find_commit(old)?.as_object(),
+                Some(CheckoutBuilder::new().safe()),
+            )?;
+            repo.set_head(&local_ref_name)?;
+            println!(
+                "Local {} at {} is ahead of origin at {}, leaving it as is.",
+                branch,
+                short_id(old),
+                short_id(new)
+            );
+            return Ok(());
+        }
+        if !repo.graph_descendant_of(new, old)? && old != new {
+            return Err(CustomError::DivergedHistory {
+                branch,
+                local: old.to_string(),
+                remote: new.to_string(),
+            });
+        }
+    }
+
+    // A fast-forward checks out the new tree against the current HEAD before moving the branch,
+    // so local modifications are kept or reported as conflicts instead of being overwritten.
+    if strategy == UpdateStrategy::FastForward {
+        repo.checkout_tree(
+            remote_commit.as_object(),
+            Some(CheckoutBuilder::new().safe()),
+        )?;
+    }
+    repo.reference(
+        &local_ref_name,
+        new,
+        true,
+        "goblin_scout: update from origin",
+    )?;
+    repo.set_head(&local_ref_name)?;
+    if strategy == UpdateStrategy::Reset {
+        repo.reset(remote_commit.as_object(), ResetType::Hard, None)?;
+    }
+
+    match local_commit {
+        Some(old) => println!(
+            "Updated {}: {} -> {}.",
+            branch,
+            short_id(old),
+            short_id(new)
+        ),
+        None => println!("Checked out {} at {}.", branch, short_id(new)),
+    }
     Ok(())
 }
 
-fn git_repo_clone(repo_url: &str, local_repo_path: &PathBuf) -> Result<Repository, CustomError> {
+// Read the default branch from `refs/remotes/origin/HEAD`, asking the remote when it is missing.
+fn remote_default_branch(
+    repo: &Repository,
+    remote: &mut Remote,
+    auth: &GitAuth,
+) -> Result<String, CustomError> {
+    const PREFIX: &str = "refs/remotes/origin/";
+
+    if let Some(target) = repo
+        .find_reference("refs/remotes/origin/HEAD")
+        .ok()
+        .and_then(|r| r.symbolic_target().map(str::to_string))
+    {
+        if let Some(branch) = target.strip_prefix(PREFIX) {
+            return Ok(branch.to_string());
+        }
+    }
+
+    let connection = remote.connect_auth(Direction::Fetch, Some(auth.callbacks()), None)?;
+    let default_ref = connection.default_branch()?;
+    drop(connection);
+
+    let branch = default_ref
+        .as_str()
+        .and_then(|r| r.strip_prefix("refs/heads/"))
+        .ok_or_else(|| {
+            CustomError::StrError("Could not determine the remote's default branch".to_string())
+        })?
+        .to_string();
+
+    repo.reference_symbolic(
+        "refs/remotes/origin/HEAD",
+        &format!("{}{}", PREFIX, branch),
+        true,
+        "goblin_scout: record remote default branch",
+    )?;
+    Ok(branch)
+}
+
+// Re-map git errors raised while talking to the remote into authentication errors.
+fn auth_error(auth: &GitAuth, url: &str, error: CustomError) -> CustomError {
+    match error {
+        CustomError::GitError(e) => auth.map_error(url, e),
+        other => other,
+    }
+}
+
+fn short_id(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+fn git_repo_clone(
+    repo_url: &str,
+    local_repo_path: &Path,
+    auth: &GitAuth,
+) -> Result<Repository, CustomError> {
     println!("No existing repo found. Cloning...");
-    Ok(Repository::clone(repo_url, local_repo_path)?)
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(auth.callbacks());
+    RepoBuilder::new()
+        .fetch_options(fetch_options)
+        .clone(repo_url, local_repo_path)
+        .map_err(|e| auth.map_error(repo_url, e))
+}
+
+/// The commit being documented and the name used for it in file URLs.
+pub struct Snapshot {
+    pub commit: Oid,
+    pub label: String,
+}
+
+// Resolve a tag, branch or SHA to a commit, or use HEAD when no revision is given. Branches that
+// only exist on origin, as in a fresh clone, are found under `refs/remotes/origin/`.
+// Tags and branches keep their name as the URL label, anything else uses the full SHA.
+pub fn git_snapshot(repo: &Repository, rev: Option<&str>) -> Result<Snapshot, CustomError> {
+    let Some(rev) = rev else {
+        let head = repo.head()?;
+        return Ok(Snapshot {
+            commit: head.peel_to_commit()?.id(),
+            label: head.shorthand().unwrap_or("main").to_string(),
+        });
+    };
+
+    let commit = repo
+        .revparse_single(rev)
+        .or_else(|e| {
+            repo.revparse_single(&format!("refs/remotes/origin/{}", rev))
+                .map_err(|_| e)
+        })
+        .and_then(|obj| obj.peel_to_commit())
+        .map_err(|e| CustomError::StrError(format!("Failed to resolve revision '{}': {}", rev, e)))?
+        .id();
+
+    let is_named = ["refs/tags/", "refs/heads/", "refs/remotes/origin/"]
+        .iter()
+        .any(|prefix| repo.find_reference(&format!("{}{}", prefix, rev)).is_ok());
+    let label = if is_named {
+        rev.to_string()
+    } else {
+        commit.to_string()
+    };
+
+    Ok(Snapshot { commit, label })
+}
+
+// Repository name from the last segment of a URL, scp-like remote or path, without `.git`.
+// Every source derives its name this way, so one repository gets the same output directory
+// and titles however it was opened.
+pub fn repo_name(location: &str) -> String {
+    let last = location
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\', ':'])
+        .next()
+        .unwrap_or_default();
+    let name = if last.to_ascii_lowercase().ends_with(".git") {
+        &last[..last.len() - 4]
+    } else {
+        last
+    };
+    if name.is_empty() {
+        "unknown_repo".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// Name of the repository's checkout directory, or of the bare repository without `.git`.
+pub fn git_repo_name(repo: &Repository) -> String {
+    let path = repo.workdir().unwrap_or_else(|| repo.path());
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    repo_name(&path.to_string_lossy())
 }
 
 pub fn git_latest_release(repo: &Repository) -> Result<(String, String), CustomError> {
     let tags = repo.tag_names(None)?;
-    if let Some(Some(latest_tag)) = tags.iter().last() {
+    if let Some(Some(latest_tag)) = tags.iter().next_back() {
         let obj = repo.revparse_single(latest_tag)?;
         let commit = obj.peel_to_commit()?;
         let timestamp = commit.time();
@@ -65,41 +307,191 @@ pub fn git_latest_release(repo: &Repository) -> Result<(String, String), CustomE
     }
 }
 
-pub fn git_contributors(repo: &Repository) -> Result<HashMap<String, usize>, CustomError> {
+// Count commits per author in the history reachable from the given commit.
+pub fn git_contributors(
+    repo: &Repository,
+    commit: Oid,
+) -> Result<HashMap<String, usize>, CustomError> {
     let mut revwalk = repo.revwalk()?;
-    revwalk.push_head()?;
+    revwalk.push(commit)?;
 
     let mut contributors: HashMap<String, usize> = HashMap::new();
 
-    for commit_id in revwalk {
-        if let Ok(id) = commit_id {
-            let obj = repo.find_object(id, Some(ObjectType::Commit))?;
-            let commit = obj.into_commit().expect("It's a commit object");
-            let author = commit.author().name().unwrap_or("Unknown").to_string();
-            *contributors.entry(author).or_insert(0) += 1;
-        }
+    for id in revwalk.flatten() {
+        let obj = repo.find_object(id, Some(ObjectType::Commit))?;
+        let commit = obj.into_commit().expect("It's a commit object");
+        let author = commit.author().name().unwrap_or("Unknown").to_string();
+        *contributors.entry(author).or_insert(0) += 1;
     }
 
     Ok(contributors)
 }
 
-// Process files in a repository and execute a callback for each file.
-pub fn process_repo_files<F>(repo: &Repository, mut callback: F) -> Result<(), CustomError>
-where
-    F: FnMut(&DirEntry) -> Result<(), CustomError>,
-{
-    let repo_path = repo.path().parent().unwrap_or_else(|| Path::new(""));
-
-    for entry in WalkDir::new(&repo_path) {
-        let entry = entry.map_err(|e| CustomError::StrError(e.to_string()))?;
+// List the files of a repository: the checkout, or the snapshot's tree when `file_source` is
+// `tree`, a revision was requested or the repository is bare.
+pub fn git_repo_files<'a>(
+    repo: &'a Repository,
+    snapshot: &Snapshot,
+    settings: &Settings,
+) -> Result<Vec<RepoFile<'a>>, CustomError> {
+    match (settings.file_source, repo.workdir()) {
+        (FileSource::Worktree, Some(workdir)) if settings.rev.is_none() => directory_files(workdir),
+        _ => tree_files(repo, &repo.find_commit(snapshot.commit)?.tree()?),
+    }
+}
 
-        let should_skip = fops_skip(&entry.path()).unwrap_or(true);
+// List the blobs tracked in a tree, read straight from the object database.
+// Symlinks and submodules are skipped.
+pub fn tree_files<'a>(repo: &'a Repository, tree: &Tree) -> Result<Vec<RepoFile<'a>>, CustomError> {
+    let odb = repo.odb()?;
+    let mut entries = Vec::new();
 
-        if should_skip || !entry.path().is_file() {
-            continue;
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let mode = entry.filemode();
+        if entry.kind() == Some(ObjectType::Blob)
+            && (mode == FILEMODE_BLOB || mode == FILEMODE_BLOB_EXECUTABLE)
+        {
+            let path = PathBuf::from(root).join(entry.name().unwrap_or_default());
+            entries.push((path, entry.id(), mode));
         }
+        TreeWalkResult::Ok
+    })?;
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+        .into_iter()
+        .map(|(path, oid, mode)| {
+            let (size, _) = odb.read_header(oid)?;
+            Ok(RepoFile {
+                path,
+                oid,
+                mode,
+                size: size as u64,
+                location: FileLocation::Blob(repo),
+            })
+        })
+        .collect()
+}
 
-        callback(&entry)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::auth::AuthSettings;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    // Commit `content` as `file_name` on top of `reference` without touching any worktree, so
+    // it works for bare repositories too. Only top-level files are supported.
+    fn commit_file(repo: &Repository, reference: &str, file_name: &str, content: &str) -> Oid {
+        let parent = repo
+            .find_reference(reference)
+            .and_then(|r| r.resolve())
+            .and_then(|r| r.peel_to_commit())
+            .ok();
+        let mut builder = repo
+            .treebuilder(parent.as_ref().map(|c| c.tree().unwrap()).as_ref())
+            .unwrap();
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        builder.insert(file_name, blob, FILEMODE_BLOB).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some(reference),
+            &signature,
+            &signature,
+            file_name,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    // A bare `origin` with one commit and a clone of it, in a directory removed with the
+    // returned `TempDir`.
+    fn origin_and_clone() -> (TempDir, Repository, Repository) {
+        let dir = TempDir::new().unwrap();
+        let origin = Repository::init_bare(dir.path().join("origin.git")).unwrap();
+        commit_file(&origin, "HEAD", "a.rs", "fn a() {}\n");
+        let local =
+            Repository::clone(origin.path().to_str().unwrap(), dir.path().join("local")).unwrap();
+        (dir, origin, local)
+    }
+
+    fn update(local: &Repository, strategy: UpdateStrategy) -> Result<(), CustomError> {
+        let auth_settings = AuthSettings::default();
+        fetch_updates_from_remote(local, strategy, &GitAuth::new(&auth_settings))
+    }
+
+    fn head(repo: &Repository) -> Oid {
+        repo.head().unwrap().peel_to_commit().unwrap().id()
+    }
+
+    #[test]
+    fn test_fast_forward_moves_to_origin() {
+        let (_dir, origin, local) = origin_and_clone();
+        let new = commit_file(&origin, "HEAD", "b.rs", "fn b() {}\n");
+
+        update(&local, UpdateStrategy::FastForward).unwrap();
+        assert_eq!(head(&local), new);
+        assert!(local.workdir().unwrap().join("b.rs").is_file());
+    }
+
+    #[test]
+    fn test_local_commits_ahead_of_origin_are_kept() {
+        let (_dir, _origin, local) = origin_and_clone();
+        let ahead = commit_file(&local, "HEAD", "a.rs", "fn a() {}\n");
+
+        update(&local, UpdateStrategy::FastForward).unwrap();
+        assert_eq!(head(&local), ahead);
+    }
+
+    #[test]
+    fn test_diverged_history_fails_unless_reset() {
+        let (_dir, origin, local) = origin_and_clone();
+        let remote = commit_file(&origin, "HEAD", "b.rs", "fn b() {}\n");
+        let diverged = commit_file(&local, "HEAD", "c.rs", "fn c() {}\n");
+
+        let error = update(&local, UpdateStrategy::FastForward).unwrap_err();
+        assert!(matches!(error, CustomError::DivergedHistory { .. }));
+        assert_eq!(head(&local), diverged);
+
+        update(&local, UpdateStrategy::Reset).unwrap();
+        assert_eq!(head(&local), remote);
+        assert!(!local.workdir().unwrap().join("c.rs").exists());
+    }
+
+    #[test]
+    fn test_update_fetches_other_branches_and_tags() {
+        let (_dir, origin, local) = origin_and_clone();
+        let feature = commit_file(&origin, "refs/heads/feature-x", "b.rs", "fn b() {}\n");
+        let release = origin.find_object(feature, None).unwrap();
+        origin.tag_lightweight("v2.0.0", &release, false).unwrap();
+
+        update(&local, UpdateStrategy::FastForward).unwrap();
+        let fetched = |name: &str| {
+            local
+                .find_reference(name)
+                .and_then(|r| r.peel_to_commit())
+                .map(|c| c.id())
+                .ok()
+        };
+        assert_eq!(fetched("refs/remotes/origin/feature-x"), Some(feature));
+        assert_eq!(fetched("refs/tags/v2.0.0"), Some(feature));
+        assert_eq!(git_latest_release(&local).unwrap().0, "v2.0.0");
+    }
+
+    #[test]
+    fn test_rev_resolves_branches_only_on_origin() {
+        let (dir, origin, _local) = origin_and_clone();
+        let feature = commit_file(&origin, "refs/heads/feature-x", "b.rs", "fn b() {}\n");
+        let fresh =
+            Repository::clone(origin.path().to_str().unwrap(), dir.path().join("fresh")).unwrap();
+        assert!(fresh.find_reference("refs/heads/feature-x").is_err());
+
+        let snapshot = git_snapshot(&fresh, Some("feature-x")).unwrap();
+        assert_eq!(snapshot.commit, feature);
+        assert_eq!(snapshot.label, "feature-x");
+        assert!(git_snapshot(&fresh, Some("no-such-branch")).is_err());
     }
-    Ok(())
 }
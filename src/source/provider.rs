@@ -0,0 +1,460 @@
+// source/provider.rs
+
+use git2::Repository;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::source::archive::{archive_extension, extract_archive, ARCHIVE_EXTENSIONS};
+use crate::source::files::{directory_files, select_files, RepoFile};
+use crate::source::git::{
+    git_contributors, git_latest_release, git_repo_check, git_repo_files, git_repo_name,
+    git_snapshot, repo_name, RepositoryDetails, Snapshot,
+};
+use crate::tools::config::Settings;
+use crate::tools::errors::CustomError;
+
+/// Repository-level metadata shared by every file of a source.
+pub struct SourceMetadata {
+    pub name: String,
+    /// URL of the `origin` remote as configured, when the source has one.
+    pub remote_url: Option<String>,
+    /// Commit being documented, when the source is a git repository.
+    pub snapshot: Option<Snapshot>,
+    pub contributors: HashMap<String, usize>,
+    pub latest_release: String,
+    pub release_date: String,
+}
+
+impl SourceMetadata {
+    // Metadata for sources without git history.
+    fn without_history(name: &str, settings: &Settings) -> Result<Self, CustomError> {
+        reject_rev(name, settings)?;
+
+        Ok(SourceMetadata {
+            name: name.to_string(),
+            remote_url: None,
+            snapshot: None,
+            contributors: HashMap::new(),
+            latest_release: "No Release".to_string(),
+            release_date: "N/A".to_string(),
+        })
+    }
+}
+
+/// Somewhere the files to document come from.
+pub trait Source {
+    /// Name used for output directories and titles.
+    fn name(&self) -> &str;
+
+    /// Directory holding the files on disk, where a source-local `goblin.toml` may live.
+    fn root(&self) -> Option<&Path>;
+
+    fn metadata(&self, settings: &Settings) -> Result<SourceMetadata, CustomError>;
+
+    /// Files to document in path order, after include/exclude rules, without binaries.
+    fn files(&self, settings: &Settings) -> Result<Vec<RepoFile<'_>>, CustomError>;
+}
+
+/// A git repository already on disk, documented as-is without fetching.
+pub struct LocalGitSource {
+    name: String,
+    repo: Repository,
+}
+
+impl LocalGitSource {
+    pub fn open(path: &Path) -> Result<Self, CustomError> {
+        let repo = Repository::open(path)?;
+        Ok(LocalGitSource {
+            name: git_repo_name(&repo),
+            repo,
+        })
+    }
+
+    // Clone a git bundle into `<cache_dir>/bundles/<name>`. libgit2 cannot read bundles, so
+    // this uses the git command line.
+    pub fn from_bundle(bundle: &Path, settings: &Settings) -> Result<Self, CustomError> {
+        let name = source_name(bundle);
+        let dest = settings.cache_dir.join("bundles").join(&name);
+        if dest.exists() {
+            std::fs::remove_dir_all(&dest)?;
+        }
+
+        println!("Cloning bundle {}...", bundle.display());
+        let status = Command::new("git")
+            .arg("clone")
+            .arg("--quiet")
+            .arg("--")
+            .arg(bundle)
+            .arg(&dest)
+            .status()
+            .map_err(|e| CustomError::StrError(format!("Failed to run git: {}", e)))?;
+        if !status.success() {
+            return Err(CustomError::StrError(format!(
+                "git clone of bundle {} failed with {}",
+                bundle.display(),
+                status
+            )));
+        }
+
+        Ok(LocalGitSource {
+            name,
+            repo: Repository::open(&dest)?,
+        })
+    }
+
+    pub fn repository(&self) -> &Repository {
+        &self.repo
+    }
+}
+
+impl Source for LocalGitSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.repo.workdir()
+    }
+
+    fn metadata(&self, settings: &Settings) -> Result<SourceMetadata, CustomError> {
+        let remote_url = self
+            .repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_string));
+
+        let snapshot = git_snapshot(&self.repo, settings.rev.as_deref())?;
+        let contributors = git_contributors(&self.repo, snapshot.commit)?;
+        let (latest_release, release_date) = git_latest_release(&self.repo)?;
+
+        Ok(SourceMetadata {
+            name: self.name.clone(),
+            remote_url,
+            snapshot: Some(snapshot),
+            contributors,
+            latest_release,
+            release_date,
+        })
+    }
+
+    fn files(&self, settings: &Settings) -> Result<Vec<RepoFile<'_>>, CustomError> {
+        let snapshot = git_snapshot(&self.repo, settings.rev.as_deref())?;
+        let files = git_repo_files(&self.repo, &snapshot, settings)?;
+        select_files(files, settings)
+    }
+}
+
+/// A remote repository, cloned or updated under the cache directory.
+pub struct GitRemoteSource {
+    details: RepositoryDetails,
+    local: LocalGitSource,
+}
+
+impl GitRemoteSource {
+    pub fn new(url: &str, settings: &Settings) -> Result<Self, CustomError> {
+        let details = RepositoryDetails::new(url, settings);
+        let repo = git_repo_check(&details, settings)?;
+
+        Ok(GitRemoteSource {
+            local: LocalGitSource {
+                name: details.name.clone(),
+                repo,
+            },
+            details,
+        })
+    }
+
+    pub fn details(&self) -> &RepositoryDetails {
+        &self.details
+    }
+}
+
+impl Source for GitRemoteSource {
+    fn name(&self) -> &str {
+        self.local.name()
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.local.root()
+    }
+
+    fn metadata(&self, settings: &Settings) -> Result<SourceMetadata, CustomError> {
+        self.local.metadata(settings)
+    }
+
+    fn files(&self, settings: &Settings) -> Result<Vec<RepoFile<'_>>, CustomError> {
+        self.local.files(settings)
+    }
+}
+
+/// A plain directory without git history.
+pub struct DirectorySource {
+    name: String,
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(root: &Path) -> Self {
+        DirectorySource {
+            name: source_name(root),
+            root: root.to_path_buf(),
+        }
+    }
+}
+
+impl Source for DirectorySource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    fn metadata(&self, settings: &Settings) -> Result<SourceMetadata, CustomError> {
+        SourceMetadata::without_history(&self.name, settings)
+    }
+
+    fn files(&self, settings: &Settings) -> Result<Vec<RepoFile<'_>>, CustomError> {
+        reject_rev(&self.name, settings)?;
+        select_files(directory_files(&self.root)?, settings)
+    }
+}
+
+/// A source tarball or zip, extracted to `<cache_dir>/archives/<name>`.
+pub struct ArchiveSource {
+    directory: DirectorySource,
+}
+
+impl ArchiveSource {
+    pub fn new(archive: &Path, settings: &Settings) -> Result<Self, CustomError> {
+        let name = source_name(archive);
+        let dest = settings.cache_dir.join("archives").join(&name);
+
+        println!("Extracting {}...", archive.display());
+        let root = extract_archive(archive, &dest)?;
+
+        Ok(ArchiveSource {
+            directory: DirectorySource { name, root },
+        })
+    }
+}
+
+impl Source for ArchiveSource {
+    fn name(&self) -> &str {
+        self.directory.name()
+    }
+
+    fn root(&self) -> Option<&Path> {
+        self.directory.root()
+    }
+
+    fn metadata(&self, settings: &Settings) -> Result<SourceMetadata, CustomError> {
+        self.directory.metadata(settings)
+    }
+
+    fn files(&self, settings: &Settings) -> Result<Vec<RepoFile<'_>>, CustomError> {
+        self.directory.files(settings)
+    }
+}
+
+// Revisions only exist for git sources.
+fn reject_rev(name: &str, settings: &Settings) -> Result<(), CustomError> {
+    match &settings.rev {
+        Some(rev) => Err(CustomError::ConfigError(format!(
+            "Revision '{}' requested, but {} is not a git repository",
+            rev, name
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Pick the source for a remote URL, `file://` URL, local repository or directory, git bundle
+// or source archive.
+pub fn open_source(spec: &str, settings: &Settings) -> Result<Box<dyn Source>, CustomError> {
+    let path = PathBuf::from(spec.strip_prefix("file://").unwrap_or(spec));
+
+    if !path.exists() {
+        if spec.starts_with("file://") || !is_remote_url(spec) {
+            return Err(CustomError::StrError(format!("{} does not exist", spec)));
+        }
+        return Ok(Box::new(GitRemoteSource::new(spec, settings)?));
+    }
+
+    if path.is_file() {
+        if archive_extension(&path).is_some() {
+            return Ok(Box::new(ArchiveSource::new(&path, settings)?));
+        }
+        if path.extension().is_some_and(|ext| ext == "bundle") {
+            return Ok(Box::new(LocalGitSource::from_bundle(&path, settings)?));
+        }
+        return Err(CustomError::StrError(format!(
+            "Unsupported source file {}, expected a git bundle or archive",
+            path.display()
+        )));
+    }
+
+    match Repository::open(&path) {
+        Ok(_) => Ok(Box::new(LocalGitSource::open(&path)?)),
+        Err(_) => Ok(Box::new(DirectorySource::new(&path))),
+    }
+}
+
+// A `scheme://` URL or an scp-style `user@host:path` remote. Anything else is taken as a path.
+fn is_remote_url(spec: &str) -> bool {
+    if let Some((scheme, rest)) = spec.split_once("://") {
+        return !scheme.is_empty()
+            && !rest.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    }
+    match spec.split_once(':') {
+        Some((user_host, path)) => match user_host.split_once('@') {
+            Some((user, host)) => {
+                !user.is_empty() && !host.is_empty() && !user_host.contains('/') && !path.is_empty()
+            }
+            None => false,
+        },
+        None => false,
+    }
+}
+
+// Name a source after its file or directory, without archive, bundle or `.git` extensions.
+fn source_name(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let file_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown_repo");
+
+    let lower = file_name.to_ascii_lowercase();
+    let suffix = ARCHIVE_EXTENSIONS
+        .iter()
+        .chain(&[".bundle"])
+        .find(|suffix| lower.ends_with(*suffix));
+
+    match suffix {
+        Some(suffix) => repo_name(&file_name[..file_name.len() - suffix.len()]),
+        None => repo_name(file_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use std::fs;
+    use tempfile::TempDir;
+
+    // A repository in `dir` with one commit adding `a.rs`.
+    fn commit_repo(dir: &Path) -> Repository {
+        let repo = Repository::init(dir).unwrap();
+        fs::write(dir.join("a.rs"), "fn a() {}\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.rs")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "add a", &tree, &[])
+            .unwrap();
+        drop(tree);
+        repo
+    }
+
+    fn file_paths(source: &dyn Source, settings: &Settings) -> Vec<PathBuf> {
+        let files = source.files(settings).unwrap();
+        files.into_iter().map(|file| file.path).collect()
+    }
+
+    #[test]
+    fn test_open_source_picks_source_and_name() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        let settings = Settings {
+            cache_dir: dir.join("cache"),
+            ..Default::default()
+        };
+
+        let plain = dir.join("plain");
+        fs::create_dir_all(&plain).unwrap();
+        fs::write(plain.join("b.rs"), "fn b() {}\n").unwrap();
+        let source = open_source(plain.to_str().unwrap(), &settings).unwrap();
+        assert_eq!(source.name(), "plain");
+        assert!(source.metadata(&settings).unwrap().snapshot.is_none());
+        assert_eq!(file_paths(source.as_ref(), &settings), [Path::new("b.rs")]);
+
+        let work = dir.join("proj");
+        commit_repo(&work);
+        let source = open_source(&format!("file://{}", work.display()), &settings).unwrap();
+        assert_eq!(source.name(), "proj");
+        assert!(source.metadata(&settings).unwrap().snapshot.is_some());
+
+        let bare = dir.join("proj.git");
+        Repository::init_bare(&bare).unwrap();
+        assert_eq!(
+            open_source(bare.to_str().unwrap(), &settings)
+                .unwrap()
+                .name(),
+            "proj"
+        );
+
+        let bundle = dir.join("proj.bundle");
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&work)
+            .args(["bundle", "create", "--quiet"])
+            .arg(&bundle)
+            .arg("HEAD")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let source = open_source(bundle.to_str().unwrap(), &settings).unwrap();
+        assert_eq!(source.name(), "proj");
+        assert_eq!(
+            source.root(),
+            Some(dir.join("cache/bundles/proj/").as_path())
+        );
+        assert_eq!(file_paths(source.as_ref(), &settings), [Path::new("a.rs")]);
+
+        let archive = dir.join("proj-1.0.tar.gz");
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            fs::File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        ));
+        tar.append_dir_all("proj-1.0", &plain).unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        let source = open_source(archive.to_str().unwrap(), &settings).unwrap();
+        assert_eq!(source.name(), "proj-1.0");
+        assert_eq!(file_paths(source.as_ref(), &settings), [Path::new("b.rs")]);
+
+        let remote = RepositoryDetails::new("https://git.example.com/org/proj.git", &settings);
+        assert_eq!(remote.name, "proj");
+        assert_eq!(remote.local_path, dir.join("cache/proj"));
+        assert_eq!(repo_name("git@example.com:proj.git/"), "proj");
+    }
+
+    #[test]
+    fn test_only_urls_are_treated_as_remotes() {
+        assert!(is_remote_url("https://github.com/org/proj.git"));
+        assert!(is_remote_url("ssh://git@example.com/org/proj.git"));
+        assert!(is_remote_url("git+ssh://example.com/proj"));
+        assert!(is_remote_url("git@github.com:org/proj.git"));
+        assert!(!is_remote_url("proj"));
+        assert!(!is_remote_url("./repos/proj"));
+        assert!(!is_remote_url("repos/user@host:proj"));
+        assert!(!is_remote_url("C:\\repos\\proj"));
+
+        let temp = TempDir::new().unwrap();
+        let settings = Settings {
+            cache_dir: temp.path().join("cache"),
+            ..Default::default()
+        };
+        let missing = temp.path().join("no-such-dir");
+        let error = open_source(missing.to_str().unwrap(), &settings)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("does not exist"), "{}", error);
+        assert!(!settings.cache_dir.exists());
+    }
+}
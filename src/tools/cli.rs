@@ -0,0 +1,322 @@
+// tools/cli.rs
+
+use crate::source::url::Host;
+use crate::tools::config::{
+    AuthConfig, ChunkConfig, Compression, ConfigLayer, FileSource, FrontmatterFormat, IdMode,
+    JsonlRecords, Mode, SettingsLoader, UpdateStrategy,
+};
+use crate::tools::errors::CustomError;
+use crate::trans_md::languages::Category;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Generate markdown documentation and datasets from git repositories.
+///
+/// Running without a subcommand falls back to the interactive prompts.
+#[derive(Debug, Parser)]
+#[command(name = "goblin_scout", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Generate a single markdown file containing every source file.
+    Single(SingleArgs),
+    /// Generate one markdown file per source file.
+    Multi(RepoArgs),
+    /// Generate chunked dataset markdown, optionally exported to JSON, JSON Lines, Parquet or Arrow.
+    Dataset(DatasetArgs),
+    /// Generate output using the mode set in `goblin.toml` or `--mode`.
+    Run(RunArgs),
+    /// Convert a directory of dataset markdown into a JSON file.
+    ToJson(ToJsonArgs),
+    /// Prompt for the repository and output mode on stdin.
+    Interactive(ConfigArgs),
+    /// Build a language map from GitHub Linguist's languages.yml and report what changed.
+    ImportLanguages(ImportLanguagesArgs),
+}
+
+/// Config file and profile selection.
+#[derive(Debug, Default, Args)]
+pub struct ConfigArgs {
+    /// Config file to use instead of `./goblin.toml`.
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Named profile from the config file, e.g. `docs` or `training`.
+    #[arg(short, long)]
+    pub profile: Option<String>,
+}
+
+impl ConfigArgs {
+    pub fn settings_loader(&self, overrides: ConfigLayer) -> Result<SettingsLoader, CustomError> {
+        SettingsLoader::new(self.config.as_deref(), self.profile.clone(), overrides)
+    }
+}
+
+/// Arguments shared by every subcommand that documents a source.
+#[derive(Debug, Args)]
+pub struct RepoArgs {
+    /// Git URL to clone or update, or a local repository, directory, `file://` URL, git bundle
+    /// or source archive (.tar, .tar.gz, .tgz, .zip).
+    #[arg(short, long, visible_alias = "url", short_alias = 'u')]
+    pub source: String,
+
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Directory where repositories are cloned [default: repositories].
+    #[arg(long)]
+    pub repos_dir: Option<PathBuf>,
+
+    /// Root directory for generated output [default: markdown].
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// List files from the checkout or from the committed tree [default: worktree].
+    #[arg(long, value_enum)]
+    pub file_source: Option<FileSource>,
+
+    /// Tag, branch or SHA to document instead of HEAD. Implies `--file-source tree`.
+    #[arg(long)]
+    pub rev: Option<String>,
+
+    /// How to update an existing clone [default: fast-forward].
+    #[arg(long, value_enum)]
+    pub update: Option<UpdateStrategy>,
+
+    /// SSH private key to try after the SSH agent.
+    #[arg(long)]
+    pub ssh_key: Option<PathBuf>,
+
+    /// Environment variable holding an HTTPS access token.
+    #[arg(long, value_name = "VAR")]
+    pub token_env: Option<String>,
+
+    /// Only process paths matching this glob. May be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip paths matching this glob. May be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Dataset chunk length at which to start looking for a newline.
+    #[arg(long)]
+    pub chunk_min: Option<usize>,
+
+    /// Preferred dataset chunk length.
+    #[arg(long)]
+    pub chunk_target: Option<usize>,
+
+    /// Maximum dataset chunk length.
+    #[arg(long)]
+    pub chunk_max: Option<usize>,
+
+    /// Comma separated list of header fields to emit.
+    #[arg(long, value_delimiter = ',')]
+    pub header_fields: Vec<String>,
+
+    /// Format of the metadata block at the top of generated files [default: yaml].
+    #[arg(long, value_enum)]
+    pub frontmatter: Option<FrontmatterFormat>,
+
+    /// Hosting service whose file URL layout to use, when it cannot be detected from the remote.
+    #[arg(long, value_enum)]
+    pub host: Option<Host>,
+
+    /// File URL template for self-hosted forges, using {base}, {host}, {repo}, {rev} and {path}.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub url_template: Option<String>,
+
+    /// Generate random UUIDs or derive them from content [default: random].
+    #[arg(long, value_enum)]
+    pub ids: Option<IdMode>,
+
+    /// Regenerate every file instead of only those changed since the last run.
+    #[arg(long)]
+    pub full: bool,
+
+    /// Number of worker threads used to render files [default: one per CPU core].
+    #[arg(short = 'j', long)]
+    pub workers: Option<usize>,
+
+    /// JSON map of languages to extensions merged over the built-in one.
+    #[arg(long, value_name = "FILE")]
+    pub language_map: Option<PathBuf>,
+
+    /// Only process files in this language category. May be repeated.
+    #[arg(long, value_enum, value_name = "CATEGORY")]
+    pub include_category: Vec<Category>,
+
+    /// Skip files in this language category. May be repeated.
+    #[arg(long, value_enum, value_name = "CATEGORY")]
+    pub exclude_category: Vec<Category>,
+}
+
+impl RepoArgs {
+    // Settings given on the command line, which take precedence over any config file.
+    pub fn overrides(&self) -> ConfigLayer {
+        let non_empty = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        let categories = |values: &Vec<Category>| (!values.is_empty()).then(|| values.clone());
+
+        ConfigLayer {
+            cache_dir: self.repos_dir.clone(),
+            output_dir: self.output_dir.clone(),
+            file_source: self.file_source,
+            rev: self.rev.clone(),
+            update: self.update,
+            include: non_empty(&self.include),
+            exclude: non_empty(&self.exclude),
+            chunk: ChunkConfig {
+                min: self.chunk_min,
+                target: self.chunk_target,
+                max: self.chunk_max,
+            },
+            header_fields: non_empty(&self.header_fields),
+            frontmatter: self.frontmatter,
+            host: self.host,
+            url_template: self.url_template.clone(),
+            ids: self.ids,
+            incremental: self.full.then_some(false),
+            workers: self.workers,
+            language_map: self.language_map.clone(),
+            include_categories: categories(&self.include_category),
+            exclude_categories: categories(&self.exclude_category),
+            auth: AuthConfig {
+                ssh_key: self.ssh_key.clone(),
+                token_env: self.token_env.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn settings_loader(&self) -> Result<SettingsLoader, CustomError> {
+        self.config.settings_loader(self.overrides())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SingleArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// Output file, defaults to `<output-dir>/<repo name>.md`.
+    #[arg(short = 'f', long)]
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DatasetArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    #[command(flatten)]
+    pub outputs: DatasetOutputArgs,
+}
+
+impl DatasetArgs {
+    pub fn settings_loader(&self) -> Result<SettingsLoader, CustomError> {
+        let overrides = self.outputs.overrides(self.repo.overrides());
+        self.repo.config.settings_loader(overrides)
+    }
+}
+
+/// Structured outputs written next to the dataset markdown.
+#[derive(Debug, Args)]
+pub struct DatasetOutputArgs {
+    /// Also write the dataset as JSON to `<output-dir>/<repo name>.json`.
+    #[arg(long)]
+    pub json: bool,
+
+    /// Stream JSON Lines records, one per file or per section, to `<output-dir>/<repo name>.jsonl`.
+    #[arg(long, value_enum, value_name = "RECORDS")]
+    pub jsonl: Option<JsonlRecords>,
+
+    /// Write one row per chunk to `<output-dir>/<repo name>.parquet`.
+    #[arg(long)]
+    pub parquet: bool,
+
+    /// Write one row per chunk to `<output-dir>/<repo name>.arrow` as an Arrow IPC file.
+    #[arg(long)]
+    pub arrow: bool,
+
+    /// Rows per Parquet row group and Arrow record batch.
+    #[arg(long, value_name = "ROWS")]
+    pub row_group_size: Option<usize>,
+
+    /// Parquet compression codec.
+    #[arg(long, value_enum)]
+    pub compression: Option<Compression>,
+}
+
+impl DatasetOutputArgs {
+    // Add the output flags to the other command line settings.
+    fn overrides(&self, layer: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            json: self.json.then_some(true),
+            jsonl: self.jsonl,
+            parquet: self.parquet.then_some(true),
+            arrow: self.arrow.then_some(true),
+            row_group_size: self.row_group_size,
+            compression: self.compression,
+            ..layer
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// Output mode, overriding `mode` from the config file.
+    #[arg(short, long, value_enum)]
+    pub mode: Option<Mode>,
+
+    #[command(flatten)]
+    pub outputs: DatasetOutputArgs,
+}
+
+impl RunArgs {
+    pub fn settings_loader(&self) -> Result<SettingsLoader, CustomError> {
+        let overrides = ConfigLayer {
+            mode: self.mode,
+            ..self.outputs.overrides(self.repo.overrides())
+        };
+        self.repo.config.settings_loader(overrides)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ToJsonArgs {
+    /// Directory of dataset markdown files.
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// JSON file to write, or JSON Lines file with `--jsonl`.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Write JSON Lines, one record per file or per section, instead of a JSON array.
+    #[arg(long, value_enum, value_name = "RECORDS")]
+    pub jsonl: Option<JsonlRecords>,
+
+    /// Skip files that fail to parse and list them in `<output>.errors.json`.
+    #[arg(long)]
+    pub lenient: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportLanguagesArgs {
+    /// Local copy of Linguist's languages.yml.
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// Language map to write, e.g. src/assets/lang_maps.json. Only reports changes when omitted.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
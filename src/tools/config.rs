@@ -0,0 +1,596 @@
+// tools/config.rs
+
+use crate::source::auth::AuthSettings;
+use crate::source::url::Host;
+use crate::tools::errors::CustomError;
+use crate::tools::fops::PathFilter;
+use crate::trans_md::code_md::HEADER_FIELDS;
+use crate::trans_md::languages::{Category, CategoryPolicy};
+use clap::ValueEnum;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE_NAME: &str = "goblin.toml";
+
+/// Markdown output mode, selectable from config for the `run` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Single,
+    Multi,
+    Dataset,
+}
+
+/// Where the files to document are listed from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSource {
+    /// Walk the checked out working directory.
+    #[default]
+    Worktree,
+    /// List the blobs tracked in the commit tree, read from the object database.
+    Tree,
+}
+
+/// How an existing clone is moved to the fetched default branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateStrategy {
+    /// Fast-forward only, failing when local commits have diverged.
+    #[default]
+    FastForward,
+    /// Hard-reset to the remote branch, discarding local changes.
+    Reset,
+}
+
+/// How file and chunk UUIDs are generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum IdMode {
+    /// A new random UUID on every run.
+    #[default]
+    Random,
+    /// UUIDv5 derived from the repository, path and content, stable across runs and machines.
+    Content,
+}
+
+/// Format of the metadata block at the top of generated files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontmatterFormat {
+    /// YAML between `---` lines.
+    #[default]
+    Yaml,
+    /// TOML between `+++` lines, as used by Hugo and Zola.
+    Toml,
+    /// JSON between `;;;` lines.
+    Json,
+}
+
+impl FrontmatterFormat {
+    pub const ALL: [FrontmatterFormat; 3] = [
+        FrontmatterFormat::Yaml,
+        FrontmatterFormat::Toml,
+        FrontmatterFormat::Json,
+    ];
+
+    // Line that opens and closes the frontmatter block.
+    pub fn delimiter(&self) -> &'static str {
+        match self {
+            FrontmatterFormat::Yaml => "---",
+            FrontmatterFormat::Toml => "+++",
+            FrontmatterFormat::Json => ";;;",
+        }
+    }
+}
+
+/// Granularity of JSON Lines dataset output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonlRecords {
+    /// One line per file with all of its sections.
+    File,
+    /// One line per section with its file's metadata flattened in.
+    Section,
+}
+
+/// Compression codec for Parquet dataset output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Uncompressed,
+    #[default]
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+/// Chunk sizes, in characters, used to split files in dataset mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSettings {
+    /// Start looking for a newline to end the chunk at this length.
+    pub min: usize,
+    /// Preferred chunk length.
+    pub target: usize,
+    /// Hard upper bound when searching for a newline or whitespace.
+    pub max: usize,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        ChunkSettings {
+            min: 500,
+            target: 512,
+            max: 750,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChunkConfig {
+    pub min: Option<usize>,
+    pub target: Option<usize>,
+    pub max: Option<usize>,
+}
+
+/// The `[auth]` table, see `AuthSettings`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub username: Option<String>,
+    pub ssh_key: Option<PathBuf>,
+    pub ssh_passphrase_env: Option<String>,
+    pub token_env: Option<String>,
+}
+
+/// One layer of settings from a config file, a profile or the command line.
+/// Values left unset fall through to the layer below.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub cache_dir: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub mode: Option<Mode>,
+    pub json: Option<bool>,
+    pub jsonl: Option<JsonlRecords>,
+    pub parquet: Option<bool>,
+    pub arrow: Option<bool>,
+    pub row_group_size: Option<usize>,
+    pub compression: Option<Compression>,
+    pub file_source: Option<FileSource>,
+    pub rev: Option<String>,
+    pub update: Option<UpdateStrategy>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub chunk: ChunkConfig,
+    pub header_fields: Option<Vec<String>>,
+    pub frontmatter: Option<FrontmatterFormat>,
+    pub host: Option<Host>,
+    pub url_template: Option<String>,
+    pub ids: Option<IdMode>,
+    pub incremental: Option<bool>,
+    pub workers: Option<usize>,
+    pub language_map: Option<PathBuf>,
+    pub include_categories: Option<Vec<Category>>,
+    pub exclude_categories: Option<Vec<Category>>,
+    pub auth: AuthConfig,
+}
+
+impl ConfigLayer {
+    // Overlay `other` on top of this layer, keeping values that `other` leaves unset.
+    pub fn merge(self, other: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            cache_dir: other.cache_dir.or(self.cache_dir),
+            output_dir: other.output_dir.or(self.output_dir),
+            mode: other.mode.or(self.mode),
+            json: other.json.or(self.json),
+            jsonl: other.jsonl.or(self.jsonl),
+            parquet: other.parquet.or(self.parquet),
+            arrow: other.arrow.or(self.arrow),
+            row_group_size: other.row_group_size.or(self.row_group_size),
+            compression: other.compression.or(self.compression),
+            file_source: other.file_source.or(self.file_source),
+            rev: other.rev.or(self.rev),
+            update: other.update.or(self.update),
+            include: other.include.or(self.include),
+            exclude: other.exclude.or(self.exclude),
+            chunk: ChunkConfig {
+                min: other.chunk.min.or(self.chunk.min),
+                target: other.chunk.target.or(self.chunk.target),
+                max: other.chunk.max.or(self.chunk.max),
+            },
+            header_fields: other.header_fields.or(self.header_fields),
+            frontmatter: other.frontmatter.or(self.frontmatter),
+            host: other.host.or(self.host),
+            url_template: other.url_template.or(self.url_template),
+            ids: other.ids.or(self.ids),
+            incremental: other.incremental.or(self.incremental),
+            workers: other.workers.or(self.workers),
+            language_map: other.language_map.or(self.language_map),
+            include_categories: other.include_categories.or(self.include_categories),
+            exclude_categories: other.exclude_categories.or(self.exclude_categories),
+            auth: AuthConfig {
+                username: other.auth.username.or(self.auth.username),
+                ssh_key: other.auth.ssh_key.or(self.auth.ssh_key),
+                ssh_passphrase_env: other
+                    .auth
+                    .ssh_passphrase_env
+                    .or(self.auth.ssh_passphrase_env),
+                token_env: other.auth.token_env.or(self.auth.token_env),
+            },
+        }
+    }
+
+    // The part of a layer that a source's own `goblin.toml` may set: which files are documented
+    // and how the output is laid out. Where output is written, where repositories are cloned,
+    // credentials and files read from outside the source are left to the user running the tool.
+    pub fn output_shaping(self) -> ConfigLayer {
+        ConfigLayer {
+            mode: self.mode,
+            include: self.include,
+            exclude: self.exclude,
+            include_categories: self.include_categories,
+            exclude_categories: self.exclude_categories,
+            chunk: self.chunk,
+            header_fields: self.header_fields,
+            frontmatter: self.frontmatter,
+            ..Default::default()
+        }
+    }
+}
+
+/// A parsed `goblin.toml`: top-level settings plus named `[profile.<name>]` tables.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub base: ConfigLayer,
+    pub profiles: HashMap<String, ConfigLayer>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, CustomError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            CustomError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let parse_error =
+            |e: toml::de::Error| CustomError::ConfigError(format!("{}: {}", path.display(), e));
+
+        let mut table: toml::Table = toml::from_str(&content).map_err(parse_error)?;
+        let profiles = match table.remove("profile") {
+            Some(value) => value.try_into().map_err(parse_error)?,
+            None => HashMap::new(),
+        };
+        let base = toml::Value::Table(table).try_into().map_err(parse_error)?;
+
+        Ok(ConfigFile {
+            path: path.to_path_buf(),
+            base,
+            profiles,
+        })
+    }
+
+    // Load `goblin.toml` from the given directory if it exists.
+    pub fn find(dir: &Path) -> Result<Option<Self>, CustomError> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            Ok(Some(Self::load(&path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Base settings with the named profile applied on top, and whether the profile exists here.
+    pub fn layer(&self, profile: Option<&str>) -> (ConfigLayer, bool) {
+        match profile.and_then(|name| self.profiles.get(name)) {
+            Some(overrides) => (self.base.clone().merge(overrides.clone()), true),
+            None => (self.base.clone(), false),
+        }
+    }
+}
+
+/// Fully resolved settings used by the generators.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub cache_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub mode: Option<Mode>,
+    pub json: bool,
+    /// Stream dataset records to `<output_dir>/<name>.jsonl` while generating.
+    pub jsonl: Option<JsonlRecords>,
+    /// Write one row per chunk to `<output_dir>/<name>.parquet`.
+    pub parquet: bool,
+    /// Write the same rows to `<output_dir>/<name>.arrow` as an Arrow IPC file.
+    pub arrow: bool,
+    /// Rows per Parquet row group and per Arrow record batch.
+    pub row_group_size: usize,
+    pub compression: Compression,
+    pub file_source: FileSource,
+    /// Tag, branch or SHA to document instead of HEAD. Files are then read from its tree.
+    pub rev: Option<String>,
+    pub update: UpdateStrategy,
+    pub filter: PathFilter,
+    pub chunk: ChunkSettings,
+    /// Header fields to emit, in the generator's order. `None` emits every field.
+    pub header_fields: Option<Vec<String>>,
+    pub frontmatter: FrontmatterFormat,
+    /// Hosting service whose file URL layout to use, instead of detecting it from the remote.
+    pub host: Option<Host>,
+    /// File URL template for self-hosted forges, see `UrlBuilder`.
+    pub url_template: Option<String>,
+    pub ids: IdMode,
+    /// Regenerate only files whose content changed since the last run, using the manifest.
+    pub incremental: bool,
+    /// Threads used to render files. `None` uses one per CPU core.
+    pub workers: Option<usize>,
+    /// JSON map of languages to extensions merged over the embedded one.
+    pub language_map: Option<PathBuf>,
+    /// Language categories to include or skip, applied after detection.
+    pub categories: CategoryPolicy,
+    pub auth: AuthSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            cache_dir: PathBuf::from("repositories"),
+            output_dir: PathBuf::from("markdown"),
+            mode: None,
+            json: false,
+            jsonl: None,
+            parquet: false,
+            arrow: false,
+            row_group_size: 8192,
+            compression: Compression::default(),
+            file_source: FileSource::default(),
+            rev: None,
+            update: UpdateStrategy::default(),
+            filter: PathFilter::default(),
+            chunk: ChunkSettings::default(),
+            header_fields: None,
+            frontmatter: FrontmatterFormat::default(),
+            host: None,
+            url_template: None,
+            ids: IdMode::default(),
+            incremental: true,
+            workers: None,
+            language_map: None,
+            categories: CategoryPolicy::default(),
+            auth: AuthSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_layer(layer: ConfigLayer) -> Result<Self, CustomError> {
+        let defaults = Settings::default();
+        let default_chunk = defaults.chunk;
+
+        let chunk = ChunkSettings {
+            min: layer.chunk.min.unwrap_or(default_chunk.min),
+            target: layer.chunk.target.unwrap_or(default_chunk.target),
+            max: layer.chunk.max.unwrap_or(default_chunk.max),
+        };
+        if chunk.min == 0 || chunk.min > chunk.target || chunk.target > chunk.max {
+            return Err(CustomError::ConfigError(format!(
+                "Chunk sizes must satisfy 0 < min <= target <= max, got {}/{}/{}",
+                chunk.min, chunk.target, chunk.max
+            )));
+        }
+
+        if layer.workers == Some(0) {
+            return Err(CustomError::ConfigError(
+                "workers must be at least 1".to_string(),
+            ));
+        }
+
+        if layer.row_group_size == Some(0) {
+            return Err(CustomError::ConfigError(
+                "row_group_size must be at least 1".to_string(),
+            ));
+        }
+
+        if let Some(fields) = &layer.header_fields {
+            if let Some(unknown) = fields.iter().find(|f| !HEADER_FIELDS.contains(&f.as_str())) {
+                return Err(CustomError::ConfigError(format!(
+                    "Unknown header field '{}', expected one of: {}",
+                    unknown,
+                    HEADER_FIELDS.join(", ")
+                )));
+            }
+        }
+
+        Ok(Settings {
+            cache_dir: layer.cache_dir.unwrap_or(defaults.cache_dir),
+            output_dir: layer.output_dir.unwrap_or(defaults.output_dir),
+            mode: layer.mode,
+            json: layer.json.unwrap_or(defaults.json),
+            jsonl: layer.jsonl,
+            parquet: layer.parquet.unwrap_or(defaults.parquet),
+            arrow: layer.arrow.unwrap_or(defaults.arrow),
+            row_group_size: layer.row_group_size.unwrap_or(defaults.row_group_size),
+            compression: layer.compression.unwrap_or(defaults.compression),
+            file_source: layer.file_source.unwrap_or(defaults.file_source),
+            rev: layer.rev,
+            update: layer.update.unwrap_or(defaults.update),
+            filter: PathFilter::new(
+                &layer.include.unwrap_or_default(),
+                &layer.exclude.unwrap_or_default(),
+            )?,
+            chunk,
+            header_fields: layer.header_fields,
+            frontmatter: layer.frontmatter.unwrap_or(defaults.frontmatter),
+            host: layer.host,
+            url_template: layer.url_template,
+            ids: layer.ids.unwrap_or(defaults.ids),
+            incremental: layer.incremental.unwrap_or(defaults.incremental),
+            workers: layer.workers,
+            language_map: layer.language_map,
+            categories: CategoryPolicy {
+                include: layer.include_categories.unwrap_or_default(),
+                exclude: layer.exclude_categories.unwrap_or_default(),
+            },
+            auth: AuthSettings {
+                username: layer.auth.username,
+                ssh_key: layer.auth.ssh_key,
+                ssh_passphrase_env: layer.auth.ssh_passphrase_env,
+                token_env: layer.auth.token_env,
+            },
+        })
+    }
+
+    // Thread pool sized by the `workers` setting, or one thread per core.
+    pub fn worker_pool(&self) -> Result<ThreadPool, CustomError> {
+        ThreadPoolBuilder::new()
+            .num_threads(self.workers.unwrap_or(0))
+            .build()
+            .map_err(|e| CustomError::StrError(format!("Failed to start worker threads: {}", e)))
+    }
+}
+
+/// Resolves settings from, lowest to highest priority: the target repository's `goblin.toml`,
+/// the working directory's `goblin.toml` (or an explicit `--config`), then CLI overrides.
+/// A selected profile is applied on top of each file's base settings.
+#[derive(Debug, Default)]
+pub struct SettingsLoader {
+    profile: Option<String>,
+    local: Option<ConfigFile>,
+    overrides: ConfigLayer,
+}
+
+impl SettingsLoader {
+    pub fn new(
+        config_path: Option<&Path>,
+        profile: Option<String>,
+        overrides: ConfigLayer,
+    ) -> Result<Self, CustomError> {
+        let local = match config_path {
+            Some(path) => Some(ConfigFile::load(path)?),
+            None => ConfigFile::find(Path::new("."))?,
+        };
+
+        Ok(SettingsLoader {
+            profile,
+            local,
+            overrides,
+        })
+    }
+
+    // Settings available before the target repository has been cloned.
+    pub fn settings(&self) -> Result<Settings, CustomError> {
+        self.resolve(None, false)
+    }
+
+    // Settings including the `goblin.toml` found at the root of the source, if any.
+    pub fn settings_for_source(&self, root: Option<&Path>) -> Result<Settings, CustomError> {
+        let repo_config = match root {
+            Some(root) => ConfigFile::find(root)?,
+            None => None,
+        };
+        self.resolve(repo_config.as_ref(), true)
+    }
+
+    fn resolve(
+        &self,
+        repo_config: Option<&ConfigFile>,
+        require_profile: bool,
+    ) -> Result<Settings, CustomError> {
+        let profile = self.profile.as_deref();
+        let mut layer = ConfigLayer::default();
+        let mut profile_found = profile.is_none();
+
+        if let Some(file) = repo_config {
+            // The repository may be untrusted, so only the keys that shape its output are used.
+            let (repo_layer, found) = file.layer(profile);
+            layer = layer.merge(repo_layer.output_shaping());
+            profile_found |= found;
+        }
+        if let Some(file) = &self.local {
+            let (local_layer, found) = file.layer(profile);
+            layer = layer.merge(local_layer);
+            profile_found |= found;
+        }
+
+        // The profile may live in the repository config, which is unknown before cloning.
+        if require_profile && !profile_found {
+            if let Some(name) = profile {
+                return Err(CustomError::ConfigError(format!(
+                    "Profile '{}' not found in any {}",
+                    name, CONFIG_FILE_NAME
+                )));
+            }
+        }
+
+        Settings::from_layer(layer.merge(self.overrides.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_profile_overrides_base_settings() {
+        let file: ConfigFile = {
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join(CONFIG_FILE_NAME);
+            fs::write(
+                &path,
+                "output_dir = \"out\"\nexclude = [\"*.lock\"]\n\n\
+                 [chunk]\ntarget = 600\nmax = 800\n\n\
+                 [profile.docs]\nmode = \"multi\"\noutput_dir = \"docs\"\n",
+            )
+            .unwrap();
+            ConfigFile::load(&path).unwrap()
+        };
+
+        let (layer, found) = file.layer(Some("docs"));
+        assert!(found);
+
+        let overrides = ConfigLayer {
+            cache_dir: Some(PathBuf::from("cache")),
+            ..Default::default()
+        };
+        let settings = Settings::from_layer(layer.merge(overrides)).unwrap();
+
+        assert_eq!(settings.output_dir, PathBuf::from("docs"));
+        assert_eq!(settings.cache_dir, PathBuf::from("cache"));
+        assert_eq!(settings.mode, Some(Mode::Multi));
+        assert_eq!(settings.chunk.target, 600);
+        assert!(!settings.filter.allows(Path::new("Cargo.lock")));
+    }
+
+    #[test]
+    fn test_repository_config_only_shapes_output() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(
+            &path,
+            "output_dir = \"/etc\"\ncache_dir = \"/tmp\"\nlanguage_map = \"/etc/passwd\"\n\
+             update = \"reset\"\nheader_fields = [\"title\"]\n\n\
+             [auth]\ntoken_env = \"AWS_SECRET_ACCESS_KEY\"\nssh_key = \"/root/.ssh/id_ed25519\"\n\n\
+             [profile.docs]\nmode = \"multi\"\noutput_dir = \"/var\"\n",
+        )
+        .unwrap();
+        let repo_config = ConfigFile::load(&path).unwrap();
+        let loader = SettingsLoader {
+            profile: Some("docs".to_string()),
+            ..Default::default()
+        };
+
+        let settings = loader.resolve(Some(&repo_config), true).unwrap();
+        let defaults = Settings::default();
+        assert_eq!(settings.mode, Some(Mode::Multi));
+        assert_eq!(settings.header_fields, Some(vec!["title".to_string()]));
+        assert_eq!(settings.output_dir, defaults.output_dir);
+        assert_eq!(settings.cache_dir, defaults.cache_dir);
+        assert_eq!(settings.language_map, None);
+        assert_eq!(settings.update, UpdateStrategy::FastForward);
+        assert_eq!(settings.auth.token_env, None);
+        assert_eq!(settings.auth.ssh_key, None);
+    }
+}
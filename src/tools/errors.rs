@@ -4,8 +4,13 @@
 use std::io;
 
 // Third-party library imports
+#[cfg(feature = "parquet")]
+use arrow_schema::ArrowError;
 use git2::Error as GitError;
+#[cfg(feature = "parquet")]
+use parquet::errors::ParquetError;
 use serde_json::Error as JsonError;
+use serde_yaml::Error as YamlError;
 use thiserror::Error;
 
 /// Custom errors used throughout the application.
@@ -26,6 +31,37 @@ pub enum CustomError {
     #[error("JSON Parsing Error: {0}")]
     JsonParsingError(#[from] JsonError),
 
+    #[error("YAML Error: {0}")]
+    YamlError(#[from] YamlError),
+
+    #[cfg(feature = "parquet")]
+    #[error("Arrow Error: {0}")]
+    ArrowError(#[from] ArrowError),
+
+    #[cfg(feature = "parquet")]
+    #[error("Parquet Error: {0}")]
+    ParquetError(#[from] ParquetError),
+
     #[error("Failed to parse {0}. Reason: {1}")]
     DetailedJsonParsingError(String, String),
+
+    #[error("{path}:{line}: {message}")]
+    FrontmatterError {
+        path: String,
+        line: usize,
+        message: String,
+    },
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Authentication failed for {0}")]
+    AuthError(String),
+
+    #[error("Local branch '{branch}' at {local} has diverged from origin at {remote}; set update = \"reset\" to discard local commits")]
+    DivergedHistory {
+        branch: String,
+        local: String,
+        remote: String,
+    },
 }
@@ -1,27 +1,28 @@
 // tools/fops.rs
 
 use crate::tools::errors::CustomError;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use std::fs::{self, File};
 use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 pub fn fops_skip(file_path: &Path) -> Result<bool, CustomError> {
     let mut buffer = [0; 1024]; // Read first 1024 bytes
     let mut file = File::open(file_path)?;
-    file.read(&mut buffer)?;
-    Ok(buffer.contains(&0x00))
+    let read = file.read(&mut buffer)?;
+    Ok(buffer[..read].contains(&0x00))
 }
 
-pub fn fops_write(path: &PathBuf, content: String) -> Result<(), CustomError> {
+pub fn fops_write(path: &Path, content: String) -> Result<(), CustomError> {
     // Ensure the directory for the file exists before writing
-    fops_mkdir(&path)?;
+    fops_mkdir(path)?;
 
     let mut file = File::create(path)?;
     file.write_all(content.as_bytes())?;
     Ok(())
 }
 
-pub fn fops_mkdir(path: &PathBuf) -> Result<(), CustomError> {
+pub fn fops_mkdir(path: &Path) -> Result<(), CustomError> {
     let parent_directory = path.parent().ok_or_else(|| {
         CustomError::StrError("Failed to get parent directory of path".to_string())
     })?;
@@ -30,3 +31,56 @@ pub fn fops_mkdir(path: &PathBuf) -> Result<(), CustomError> {
     }
     Ok(())
 }
+
+/// Include/exclude glob rules applied to paths relative to the repository root.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    // An empty include list means every path is included unless excluded.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, CustomError> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_globset(include)?)
+        };
+
+        Ok(PathFilter {
+            include,
+            exclude: build_globset(exclude)?,
+        })
+    }
+
+    pub fn allows(&self, relative_path: &Path) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|set| set.is_match(relative_path));
+        included && !self.exclude.is_match(relative_path)
+    }
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        PathFilter {
+            include: None,
+            exclude: GlobSet::empty(),
+        }
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, CustomError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| {
+            CustomError::ConfigError(format!("Invalid glob pattern '{}': {}", pattern, e))
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| CustomError::ConfigError(e.to_string()))
+}
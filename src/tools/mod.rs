@@ -1,3 +0,0 @@
-mod errors;
-mod fops;
-mod ui;
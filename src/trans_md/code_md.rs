@@ -3,70 +3,90 @@
 // --------------------------
 // IMPORTS
 // --------------------------
-use crate::git::process_repo_files;
-use crate::source::git::{git_contributors, git_latest_release};
+use crate::source::files::RepoFile;
+use crate::source::provider::{Source, SourceMetadata};
+use crate::source::url::{RemoteUrl, UrlBuilder};
+use crate::tools::config::{ChunkSettings, IdMode, Settings};
 use crate::tools::errors::CustomError;
 use crate::tools::fops;
+#[cfg(feature = "parquet")]
+use crate::trans_md::columnar;
+use crate::trans_md::document::{Document, FileMetadata, Section};
+use crate::trans_md::languages::{Detection, LanguageDetector};
+use crate::trans_md::manifest::{settings_hash, snapshot_hash, IncrementalRun};
+use crate::trans_md::md_json;
+use crate::trans_md::render::{self, DocumentWriter, JsonWriter, JsonlWriter};
 
 use chrono::Utc;
-use git2::Repository;
-use std::collections::HashMap;
+use git2::Oid;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use uuid::Uuid;
-use walkdir::DirEntry;
 
 // ---------------------------
 // UTILITY GENERAL FUNCTIONS
 // ---------------------------
 
-// Return path to the assets/lang_maps.json file.
-pub fn get_asset_path() -> PathBuf {
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push("src");
-    path.push("assets");
-    path.push("lang_maps.json");
-    path
-}
-
 fn generate_uuid() -> String {
     Uuid::new_v4().hyphenated().to_string()
 }
 
-// ---------------------------
-// UTILITY FORMATING FUNCTIONS
-// ---------------------------
-
-// Determine programming language from file extension using asset mappings.
-pub fn md_lang_maps(file_extension: &str) -> Result<String, CustomError> {
-    let asset_path = get_asset_path();
-
-    // Read the content of the file.
-    let content = fs::read_to_string(&asset_path).map_err(|e| {
-        CustomError::StrError(format!("Failed to read {}: {}", asset_path.display(), e))
-    })?;
-
-    // Deserialize the JSON content into a HashMap.
-    let mapping: HashMap<String, Vec<String>> = serde_json::from_str(&content).map_err(|e| {
-        CustomError::DetailedJsonParsingError("assets/lang_maps.json".into(), e.to_string())
-    })?;
+/// Generates file and chunk UUIDs, either randomly or from content so that unchanged files and
+/// chunks keep their IDs across runs.
+struct IdGenerator {
+    mode: IdMode,
+    /// Namespace for file IDs, derived from the repository identity.
+    namespace: Uuid,
+}
 
-    let formatted_extension = if file_extension.starts_with('.') {
-        file_extension.to_string()
-    } else {
-        format!(".{}", file_extension)
-    };
+impl IdGenerator {
+    // The repository is identified by its normalized remote, so SSH and HTTPS clones agree,
+    // falling back to the source name.
+    fn new(metadata: &SourceMetadata, mode: IdMode) -> Self {
+        let identity = metadata
+            .remote_url
+            .as_deref()
+            .and_then(RemoteUrl::parse)
+            .map_or_else(|| metadata.name.clone(), |remote| remote.web_base);
+
+        IdGenerator {
+            mode,
+            namespace: Uuid::new_v5(&Uuid::NAMESPACE_URL, identity.as_bytes()),
+        }
+    }
 
-    for (language, extensions) in mapping.iter() {
-        if extensions.contains(&formatted_extension) {
-            return Ok(language.to_string());
+    // File IDs cover the path and the git blob hash of the content.
+    fn file_id(&self, path: &Path, oid: Oid) -> String {
+        match self.mode {
+            IdMode::Random => generate_uuid(),
+            IdMode::Content => {
+                let path = path.to_string_lossy().replace('\\', "/");
+                let name = format!("{}\0{}", path, oid);
+                Uuid::new_v5(&self.namespace, name.as_bytes()).to_string()
+            }
         }
     }
 
-    Ok(formatted_extension)
+    // Chunk IDs cover the file ID, the chunk's byte offset and its content.
+    fn chunk_id(&self, file_id: &str, chunk: &Chunk) -> String {
+        match self.mode {
+            IdMode::Random => generate_uuid(),
+            IdMode::Content => {
+                let namespace = Uuid::parse_str(file_id).unwrap_or(self.namespace);
+                let name = format!("{}\0{}", chunk.start_byte, chunk.content);
+                Uuid::new_v5(&namespace, name.as_bytes()).to_string()
+            }
+        }
+    }
 }
 
+// ---------------------------
+// UTILITY FORMATING FUNCTIONS
+// ---------------------------
+
 // Format top 5 contributors with more than 1 commit
 pub fn md_contrib_five(contributors: &HashMap<String, usize>) -> String {
     let mut contributors_vec: Vec<_> = contributors
@@ -74,7 +94,8 @@ pub fn md_contrib_five(contributors: &HashMap<String, usize>) -> String {
         .filter(|&(_, &count)| count > 1)
         .collect();
 
-    contributors_vec.sort_by(|a, b| b.1.cmp(a.1));
+    // Ties are broken by name, as map iteration order varies between runs.
+    contributors_vec.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
 
     contributors_vec
         .into_iter()
@@ -84,289 +105,607 @@ pub fn md_contrib_five(contributors: &HashMap<String, usize>) -> String {
         .join(" | ")
 }
 
-// Creates the markdown header for a given file, containing metadata such as title, date, tags, etc.
-fn create_markdown_header(
+// Header fields in the order they are written, selectable through `header_fields` in config.
+pub const HEADER_FIELDS: &[&str] = &[
+    "title",
+    "date",
+    "tags",
+    "category",
+    "uuid",
+    "github_name",
+    "github_url",
+    "commit",
+    "contributors",
+    "latest_release",
+    "release_date",
+    "file_path",
+    "size",
+];
+
+// Creates the metadata for a given file, such as title, date, tags, etc., written as its header.
+#[allow(clippy::too_many_arguments)]
+fn create_file_metadata(
     repo_name: &str,
     file_name: &str,
     current_datetime: &str,
-    language: &str,
+    detection: &Detection,
     file_github_url: &str,
+    commit: &str,
     contributor_list: &str,
     latest_release: &str,
     release_datetime: &str,
     relative_path: &Path,
     content_length: usize,
     file_uuid: &str,
-) -> String {
-    format!(
-        "---\n\
-        title: {} - {}\n\
-        date: {}\n\
-        tags:\n\
-        - {}\n\
-        uuid: {}\n\
-        github_name: {}\n\
-        github_url: {}\n\
-        contributors: {}\n\
-        latest_release: {}\n\
-        release_date: {}\n\
-        file_path: \"{}\"\n\
-        size: {}\n\
-        ---\n",
-        repo_name,
-        file_name,
-        current_datetime,
-        language,
-        file_uuid,
-        file_name,
-        file_github_url,
-        contributor_list,
-        latest_release,
-        release_datetime,
-        relative_path.display(),
-        content_length
-    )
+) -> FileMetadata {
+    FileMetadata {
+        title: format!("{} - {}", repo_name, file_name),
+        date: current_datetime.to_string(),
+        tags: detection.languages.clone(),
+        category: detection
+            .category
+            .map_or(String::new(), |c| c.as_str().to_string()),
+        uuid: file_uuid.to_string(),
+        github_name: file_name.to_string(),
+        github_url: file_github_url.to_string(),
+        commit: commit.to_string(),
+        contributors: contributor_list.to_string(),
+        latest_release: latest_release.to_string(),
+        release_date: release_datetime.to_string(),
+        file_path: relative_path.display().to_string(),
+        size: content_length,
+    }
+}
+
+// Revision label for file URLs and commit SHA for the header, empty for sources without git.
+fn snapshot_fields(metadata: &SourceMetadata) -> (&str, String) {
+    match &metadata.snapshot {
+        Some(snapshot) => (&snapshot.label, snapshot.commit.to_string()),
+        None => ("", String::new()),
+    }
 }
 
-// Constructs a GitHub URL for the specific file based on its relative path, the repository URL, and branch.
-fn construct_github_url(repo_url: &str, default_branch: &str, relative_path: &Path) -> String {
-    format!(
-        "{}/blob/{}/{}",
-        repo_url,
-        default_branch,
-        relative_path.to_string_lossy()
+// URL builder for a source's remote, honoring the configured host and template.
+fn url_builder(metadata: &SourceMetadata, settings: &Settings) -> UrlBuilder {
+    UrlBuilder::new(
+        metadata.remote_url.as_deref(),
+        settings.host,
+        settings.url_template.as_deref(),
     )
 }
 
+/// Per-run values shared by every document a generator builds from a source.
+struct DocumentBuilder<'a> {
+    metadata: SourceMetadata,
+    settings: &'a Settings,
+    urls: UrlBuilder,
+    ids: IdGenerator,
+    rev_label: String,
+    commit: String,
+    current_datetime: String,
+    contributor_list: String,
+}
+
+impl<'a> DocumentBuilder<'a> {
+    fn new(metadata: SourceMetadata, settings: &'a Settings) -> Self {
+        let (rev_label, commit) = snapshot_fields(&metadata);
+        DocumentBuilder {
+            rev_label: rev_label.to_string(),
+            commit,
+            urls: url_builder(&metadata, settings),
+            ids: IdGenerator::new(&metadata, settings.ids),
+            current_datetime: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            contributor_list: md_contrib_five(&metadata.contributors),
+            metadata,
+            settings,
+        }
+    }
+
+    // A file's document, with one section per chunk when `chunked` or the whole file otherwise.
+    fn build(&self, file: &LoadedFile, detection: &Detection, chunked: bool) -> Document {
+        let file_uuid = self.ids.file_id(&file.path, file.oid);
+        let file_metadata = create_file_metadata(
+            &self.metadata.name,
+            &file.file_name,
+            &self.current_datetime,
+            detection,
+            &self.urls.file_url(&self.rev_label, &file.path),
+            &self.commit,
+            &self.contributor_list,
+            &self.metadata.latest_release,
+            &self.metadata.release_date,
+            &file.path,
+            file.content.len(),
+            &file_uuid,
+        );
+
+        let sections = if chunked {
+            split_into_chunks(&file.content, &self.settings.chunk)
+                .into_iter()
+                .map(|chunk| Section {
+                    uuid: self.ids.chunk_id(&file_uuid, &chunk),
+                    permalink: self.urls.permalink(
+                        &self.commit,
+                        &file.path,
+                        chunk.start_line,
+                        chunk.end_line,
+                    ),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    start_byte: chunk.start_byte,
+                    end_byte: chunk.end_byte,
+                    content: chunk.content,
+                })
+                .collect()
+        } else {
+            vec![Section {
+                start_line: 1,
+                // An empty file still spans its first line.
+                end_line: file.content.lines().count().max(1),
+                end_byte: file.content.len(),
+                content: file.content.clone(),
+                ..Default::default()
+            }]
+        };
+
+        Document {
+            file_metadata,
+            sections,
+            fence_tag: detection.fence_tag(),
+        }
+    }
+
+    // Set the header fields that describe the snapshot rather than the file's content.
+    fn stamp(&self, metadata: &mut FileMetadata, path: &Path) {
+        metadata.date = self.current_datetime.clone();
+        metadata.github_url = self.urls.file_url(&self.rev_label, path);
+        metadata.commit = self.commit.clone();
+        metadata.contributors = self.contributor_list.clone();
+        metadata.latest_release = self.metadata.latest_release.clone();
+        metadata.release_date = self.metadata.release_date.clone();
+    }
+
+    // Rewrite the output of an unchanged file for this run's snapshot: its header and, for
+    // dataset files, the permalinks of its sections. Content, positions and IDs are kept.
+    fn restamp(&self, path: &Path, output: &Path, chunked: bool) -> Result<(), CustomError> {
+        let markdown = fs::read_to_string(output)?;
+        let header_fields = self.settings.header_fields.as_deref();
+        let restamped = if chunked {
+            let mut document = md_json::extract_from_md(output, &markdown)?;
+            self.stamp(&mut document.file_metadata, path);
+            for section in document.sections.iter_mut() {
+                section.permalink =
+                    self.urls
+                        .permalink(&self.commit, path, section.start_line, section.end_line);
+            }
+            render::dataset_markdown(&document, self.settings.frontmatter, header_fields)?
+        } else {
+            let (mut metadata, body) = md_json::parse_frontmatter(output, &markdown)?;
+            self.stamp(&mut metadata, path);
+            metadata.to_frontmatter(self.settings.frontmatter, header_fields)? + body
+        };
+        fops::fops_write(output, restamped)
+    }
+}
+
+/// A dataset chunk with its position in the source file. Lines are 1-based and inclusive,
+/// bytes are a half-open range into the UTF-8 content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk {
+    content: String,
+    start_line: usize,
+    end_line: usize,
+    start_byte: usize,
+    end_byte: usize,
+}
+
+// Splits content into chunks of roughly `target` characters, preferring to end on a newline found
+// after `min` characters, then on a newline or whitespace before `max` characters.
+fn split_into_chunks(content: &str, sizes: &ChunkSettings) -> Vec<Chunk> {
+    let chars = content.chars().collect::<Vec<_>>();
+    let mut chunks = Vec::new();
+    let mut current_pos = 0;
+    let mut line = 1;
+    let mut byte = 0;
+
+    while current_pos < chars.len() {
+        let end_pos = chunk_end(&chars, current_pos, sizes);
+        let text: String = chars[current_pos..end_pos].iter().collect();
+        let newlines = text.matches('\n').count();
+        // A trailing newline ends the chunk's last line rather than starting a new one.
+        let end_line = line + newlines - usize::from(text.ends_with('\n'));
+
+        chunks.push(Chunk {
+            start_line: line,
+            end_line,
+            start_byte: byte,
+            end_byte: byte + text.len(),
+            content: text,
+        });
+
+        line += newlines;
+        byte = chunks.last().map_or(byte, |chunk| chunk.end_byte);
+        current_pos = end_pos;
+    }
+
+    chunks
+}
+
+// Finds the end position of the chunk starting at `start`.
+fn chunk_end(chars: &[char], start: usize, sizes: &ChunkSettings) -> usize {
+    let end_pos = start + sizes.target;
+    if end_pos >= chars.len() {
+        return chars.len();
+    }
+
+    let limit = std::cmp::min(start + sizes.max, chars.len());
+    let find = |from: usize, to: usize, pred: fn(&char) -> bool| {
+        chars[from..to].iter().position(pred).map(|i| from + i)
+    };
+
+    // Look for a newline between the min and target sizes, then up to max, including it in the chunk.
+    if let Some(i) = find(start + sizes.min, end_pos + 1, |c| *c == '\n') {
+        return i + 1;
+    }
+    if let Some(i) = find(end_pos, limit, |c| *c == '\n') {
+        return i + 1;
+    }
+
+    // If no newline was found by max, look for whitespace.
+    find(end_pos, limit, |c| c.is_whitespace()).unwrap_or(end_pos)
+}
+
 // Generates the appropriate output file name for a markdown file based on its relative path.
 fn get_output_file_name(relative_path: &Path) -> String {
     format!("{}.md", relative_path.to_string_lossy())
 }
+
+// ----------------------------
+// PARALLEL PROCESSING
+// ----------------------------
+
+// Files read per batch before rendering, bounding how much content is held in memory.
+const BATCH_SIZE: usize = 256;
+
+/// A file's content, ready to be rendered on the worker pool.
+struct LoadedFile {
+    path: PathBuf,
+    oid: Oid,
+    file_name: String,
+    content: String,
+}
+
+/// A file handed to the worker pool. Files on disk are read there; blob-backed `RepoFile`s
+/// borrow the repository, which cannot be shared between threads, so they are read on the
+/// calling thread first.
+enum PendingFile {
+    Loaded(LoadedFile),
+    OnDisk(LoadedFile, PathBuf),
+}
+
+impl PendingFile {
+    fn new(file: &RepoFile) -> Result<Self, CustomError> {
+        let loaded = LoadedFile {
+            path: file.path.clone(),
+            oid: file.oid,
+            file_name: file.file_name().into_owned(),
+            content: String::new(),
+        };
+        match file.disk_path() {
+            Some(disk_path) => Ok(PendingFile::OnDisk(loaded, disk_path.to_path_buf())),
+            None => Ok(PendingFile::Loaded(LoadedFile {
+                content: file.read_to_string()?,
+                ..loaded
+            })),
+        }
+    }
+
+    fn load(self) -> Result<LoadedFile, CustomError> {
+        match self {
+            PendingFile::Loaded(loaded) => Ok(loaded),
+            PendingFile::OnDisk(loaded, disk_path) => Ok(LoadedFile {
+                content: String::from_utf8(fs::read(&disk_path)?).map_err(|_| {
+                    CustomError::StrError(format!("{} is not valid UTF-8", loaded.path.display()))
+                })?,
+                ..loaded
+            }),
+        }
+    }
+}
+
+// Detect each file's language and render it on the worker pool, handing each result to `done`
+// in the original file order so output stays deterministic.
+fn render_in_parallel<T, R, D>(
+    files: &[&RepoFile],
+    settings: &Settings,
+    detector: &LanguageDetector,
+    render: R,
+    mut done: D,
+) -> Result<(), CustomError>
+where
+    T: Send,
+    R: Fn(&LoadedFile, &Detection) -> Result<T, CustomError> + Sync,
+    D: FnMut(&LoadedFile, T) -> Result<(), CustomError>,
+{
+    let pool = settings.worker_pool()?;
+    let load_and_render = |pending: PendingFile| {
+        let file = pending.load()?;
+        let detection = detector.classify(&file.file_name, &file.content);
+        let rendered = render(&file, &detection)?;
+        Ok((file, rendered))
+    };
+
+    for batch in files.chunks(BATCH_SIZE) {
+        let pending = batch
+            .iter()
+            .map(|file| PendingFile::new(file))
+            .collect::<Result<Vec<_>, _>>()?;
+        let rendered = pool.install(|| {
+            pending
+                .into_par_iter()
+                .map(load_and_render)
+                .collect::<Result<Vec<_>, CustomError>>()
+        })?;
+
+        for (file, result) in rendered {
+            done(&file, result)?;
+        }
+    }
+    Ok(())
+}
+
+// Drop files whose language category the settings exclude. This runs before the manifest is
+// consulted, so excluded files are neither kept nor rendered and their old outputs are removed.
+// Detection needs the content, which is read on the worker pool in batches.
+fn filter_categories<'r>(
+    files: Vec<RepoFile<'r>>,
+    settings: &Settings,
+    detector: &LanguageDetector,
+) -> Result<Vec<RepoFile<'r>>, CustomError> {
+    if settings.categories.allows_all() {
+        return Ok(files);
+    }
+
+    let pool = settings.worker_pool()?;
+    let mut allowed = Vec::with_capacity(files.len());
+    for batch in files.chunks(BATCH_SIZE) {
+        let pending = batch
+            .iter()
+            .map(|file| PendingFile::new(file))
+            .collect::<Result<Vec<_>, _>>()?;
+        let batch_allowed = pool.install(|| {
+            pending
+                .into_par_iter()
+                .map(|pending| {
+                    let file = pending.load()?;
+                    let detection = detector.classify(&file.file_name, &file.content);
+                    Ok(settings.categories.allows(detection.category))
+                })
+                .collect::<Result<Vec<_>, CustomError>>()
+        })?;
+        allowed.extend(batch_allowed);
+    }
+
+    Ok(files
+        .into_iter()
+        .zip(allowed)
+        .filter_map(|(file, allowed)| allowed.then_some(file))
+        .collect())
+}
+
+// Files an incremental run has to render, in source order. Files the manifest keeps are
+// restamped on the worker pool when the snapshot moved on since the last run; any whose output
+// cannot be read back are rendered again.
+fn files_to_render<'f, 'r>(
+    files: &'f [RepoFile<'r>],
+    run: &mut IncrementalRun,
+    builder: &DocumentBuilder,
+    chunked: bool,
+) -> Result<Vec<&'f RepoFile<'r>>, CustomError> {
+    let kept: Vec<bool> = files
+        .iter()
+        .map(|file| run.keep(&file.path, file.oid))
+        .collect();
+
+    let stale = run.stale_outputs();
+    let unreadable: HashSet<PathBuf> = builder.settings.worker_pool()?.install(|| {
+        stale
+            .par_iter()
+            .filter(|(path, output)| builder.restamp(path, output, chunked).is_err())
+            .map(|(path, _)| path.clone())
+            .collect()
+    });
+    for path in &unreadable {
+        run.forget(path);
+    }
+
+    Ok(files
+        .iter()
+        .zip(kept)
+        .filter(|(file, kept)| !kept || unreadable.contains(&file.path))
+        .map(|(file, _)| file)
+        .collect())
+}
+
 // ----------------------------
 // MARKDOWN CREATION FUNCTIONS
 // ----------------------------
 
 // Generate a single markdown file from repository content.
-pub fn code_md_single_markdown(repo: &Repository) -> Result<String, CustomError> {
+pub fn code_md_single_markdown(
+    source: &dyn Source,
+    settings: &Settings,
+) -> Result<String, CustomError> {
+    let detector = LanguageDetector::for_settings(settings)?;
+    let files = filter_categories(source.files(settings)?, settings, &detector)?;
+    // The single file only shows paths, so the repository history is not read.
+    let metadata = SourceMetadata {
+        name: source.name().to_string(),
+        remote_url: None,
+        snapshot: None,
+        contributors: HashMap::new(),
+        latest_release: String::new(),
+        release_date: String::new(),
+    };
+    let builder = DocumentBuilder::new(metadata, settings);
     let mut markdown_content = String::new();
-    process_repo_files(repo, &mut |entry: &DirEntry| {
-        markdown_content.push_str(&format!(
-            "## File: {}\n\n```\n{}\n```\n",
-            entry.path().display(),
-            fs::read_to_string(entry.path())?
-        ));
-        Ok(())
-    })?;
+
+    render_in_parallel(
+        &files.iter().collect::<Vec<_>>(),
+        settings,
+        &detector,
+        |file, detection| {
+            let document = builder.build(file, detection, false);
+            Ok(render::single_markdown_entry(&document))
+        },
+        |_, entry| {
+            markdown_content.push_str(&entry);
+            Ok(())
+        },
+    )?;
     Ok(markdown_content)
 }
 
 // Generate individual markdown files from repository content maintaining file structure.
 pub fn code_md_multi_markdown(
-    repo: &Repository,
+    source: &dyn Source,
     base_output_dir: &Path,
+    settings: &Settings,
 ) -> Result<(), CustomError> {
-    let repo_name = repo
-        .workdir()
-        .and_then(|p| p.file_name())
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown_repo");
-
-    let output_dir = base_output_dir.join("multi").join(repo_name);
-    let repo_path = repo.path().parent().unwrap_or(Path::new(""));
+    let output_dir = base_output_dir.join("multi").join(source.name());
 
     fops::fops_mkdir(&output_dir)?;
 
-    let remote = repo.find_remote("origin")?;
-    let repo_url = remote.url().unwrap_or("").replace(".git", "");
-
-    let head = repo.head()?;
-    let default_branch = head.shorthand().unwrap_or("main");
-    let current_datetime = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-    let contributors = git_contributors(repo)?;
-    let (latest_release, release_datetime) = git_latest_release(repo)?;
-
-    process_repo_files(repo, |entry| {
-        let should_skip = fops::fops_skip(entry.path()).unwrap_or(true);
-
-        if should_skip {
-            return Ok(());
-        }
-
-        let content = fs::read_to_string(entry.path())?;
-        let relative_path = entry
-            .path()
-            .strip_prefix(&repo_path)
-            .unwrap_or(entry.path());
-
-        let file_github_url = construct_github_url(&repo_url, &default_branch, &relative_path);
-        let file_name = entry
-            .path()
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy();
-        let file_extension = entry
-            .path()
-            .extension()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default();
-
-        let language = md_lang_maps(file_extension).unwrap_or_else(|_| file_extension.to_string());
-        let file_uuid = generate_uuid();
-        let contributor_list = md_contrib_five(&contributors);
-        let header = create_markdown_header(
-            &repo_name,
-            &file_name,
-            &current_datetime,
-            &language,
-            &file_github_url,
-            &contributor_list,
-            &latest_release,
-            &release_datetime,
-            &relative_path,
-            content.len(),
-            &file_uuid,
-        );
-
-        let file_markdown = format!("{}\n```\n{}\n```\n", header, content);
-        let output_file_name = get_output_file_name(&relative_path);
-        let output_file_path = output_dir.join(&output_file_name);
+    let builder = DocumentBuilder::new(source.metadata(settings)?, settings);
+    let detector = LanguageDetector::for_settings(settings)?;
+    let mut run = IncrementalRun::start(
+        &output_dir,
+        settings_hash("multi", settings, &detector),
+        snapshot_hash(&builder.metadata),
+        settings.incremental,
+    );
+
+    let files = filter_categories(source.files(settings)?, settings, &detector)?;
+    let pending = files_to_render(&files, &mut run, &builder, false)?;
+
+    let render = |file: &LoadedFile, detection: &Detection| {
+        let document = builder.build(file, detection, false);
+        let file_markdown = render::file_markdown(
+            &document,
+            settings.frontmatter,
+            settings.header_fields.as_deref(),
+        )?;
+        let output_file_path = output_dir.join(get_output_file_name(&file.path));
 
         fops::fops_write(&output_file_path, file_markdown)?;
+        Ok(output_file_path)
+    };
+
+    render_in_parallel(&pending, settings, &detector, render, |file, output| {
+        run.record(&file.path, file.oid, vec![output]);
         Ok(())
     })?;
+
+    println!("{}: {}.", output_dir.display(), run.finish()?);
     Ok(())
 }
 
-// Prepare for datasets individual markdown files from repository content maintaining file structure.
+// JSON, JSON Lines, Parquet and Arrow outputs enabled in the settings, written next to the
+// dataset directory.
+fn structured_writers(
+    base_output_dir: &Path,
+    name: &str,
+    settings: &Settings,
+) -> Result<Vec<Box<dyn DocumentWriter>>, CustomError> {
+    let mut writers: Vec<Box<dyn DocumentWriter>> = Vec::new();
+    if settings.json {
+        let path = base_output_dir.join(format!("{}.json", name));
+        writers.push(Box::new(JsonWriter::create(&path)?));
+    }
+    if let Some(records) = settings.jsonl {
+        let path = base_output_dir.join(format!("{}.jsonl", name));
+        writers.push(Box::new(JsonlWriter::create(&path, records)?));
+    }
+    #[cfg(feature = "parquet")]
+    {
+        if settings.parquet {
+            let path = base_output_dir.join(format!("{}.parquet", name));
+            writers.push(columnar::parquet_writer(&path, name, settings)?);
+        }
+        if settings.arrow {
+            let path = base_output_dir.join(format!("{}.arrow", name));
+            writers.push(columnar::arrow_writer(&path, name, settings)?);
+        }
+    }
+    #[cfg(not(feature = "parquet"))]
+    if settings.parquet || settings.arrow {
+        return Err(CustomError::ConfigError(
+            "Parquet and Arrow output need a build with the `parquet` feature".to_string(),
+        ));
+    }
+    Ok(writers)
+}
+
+// Prepare for datasets individual markdown files from repository content maintaining file
+// structure, and stream the same documents to any JSON outputs.
 pub fn code_md_dataset_markdown(
-    repo: &Repository,
+    source: &dyn Source,
     base_output_dir: &Path,
+    settings: &Settings,
 ) -> Result<(), CustomError> {
-    let repo_name = repo
-        .workdir()
-        .and_then(|p| p.file_name())
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown_repo");
-
-    let output_dir = base_output_dir.join("dataset").join(repo_name);
-
-    let repo_path = repo.path().parent().unwrap_or(Path::new(""));
+    let output_dir = base_output_dir.join("dataset").join(source.name());
 
     fops::fops_mkdir(&output_dir)?;
 
-    let remote = repo.find_remote("origin")?;
-    let repo_url = remote.url().unwrap_or("").replace(".git", "");
-
-    let head = repo.head()?;
-    let default_branch = head.shorthand().unwrap_or("main");
-    let current_datetime = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-    let contributors = git_contributors(repo)?;
-    let (latest_release, release_datetime) = git_latest_release(repo)?;
+    let builder = DocumentBuilder::new(source.metadata(settings)?, settings);
+    let detector = LanguageDetector::for_settings(settings)?;
+    let mut writers = structured_writers(base_output_dir, source.name(), settings)?;
+    let keep_documents = !writers.is_empty();
+    // Structured outputs have to contain every file, so they turn the run into a full one.
+    let mut run = IncrementalRun::start(
+        &output_dir,
+        settings_hash("dataset", settings, &detector),
+        snapshot_hash(&builder.metadata),
+        settings.incremental && !keep_documents,
+    );
+
+    let files = filter_categories(source.files(settings)?, settings, &detector)?;
+    let pending = files_to_render(&files, &mut run, &builder, true)?;
+
+    let render = |file: &LoadedFile, detection: &Detection| {
+        let document = builder.build(file, detection, true);
+        let file_markdown = render::dataset_markdown(
+            &document,
+            settings.frontmatter,
+            settings.header_fields.as_deref(),
+        )?;
+        let output_file_path = output_dir.join(get_output_file_name(&file.path));
 
-    process_repo_files(repo, |entry| {
-        let should_skip = fops::fops_skip(entry.path()).unwrap_or(true);
-
-        if should_skip {
-            return Ok(());
-        }
-
-        let content = fs::read_to_string(entry.path())?;
-        let relative_path = entry
-            .path()
-            .strip_prefix(&repo_path)
-            .unwrap_or(entry.path());
-
-        let file_github_url = construct_github_url(&repo_url, &default_branch, &relative_path);
-        let file_name = entry
-            .path()
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy();
-        let file_extension = entry
-            .path()
-            .extension()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default();
-
-        let language = md_lang_maps(file_extension).unwrap_or_else(|_| file_extension.to_string());
-
-        let file_uuid = generate_uuid();
-        let contributor_list = md_contrib_five(&contributors);
-        let header = create_markdown_header(
-            &repo_name,
-            &file_name,
-            &current_datetime,
-            &language,
-            &file_github_url,
-            &contributor_list,
-            &latest_release,
-            &release_datetime,
-            &relative_path,
-            content.len(),
-            &file_uuid,
-        );
+        fops::fops_write(&output_file_path, file_markdown)?;
+        Ok((output_file_path, keep_documents.then_some(document)))
+    };
 
-        let mut split_content = Vec::new();
-        let chars = content.chars().collect::<Vec<_>>();
-        let mut current_pos = 0;
-
-        while current_pos < chars.len() {
-            let mut end_pos = current_pos + 512;
-            if end_pos > chars.len() {
-                end_pos = chars.len();
-            } else {
-                // Look for newline if we are at least 500 characters in.
-                if end_pos >= current_pos + 500 {
-                    let mut found = false;
-                    for i in current_pos + 500..=end_pos {
-                        if chars[i] == '\n' {
-                            end_pos = i + 1; // include the newline character in the current chunk
-                            found = true;
-                            break;
-                        }
-                    }
-
-                    // If no newline was found by 512, extend the search up to 750 characters for a newline.
-                    if !found && end_pos < chars.len() {
-                        for i in end_pos..std::cmp::min(current_pos + 750, chars.len()) {
-                            if chars[i] == '\n' {
-                                end_pos = i + 1;
-                                found = true;
-                                break;
-                            }
-                        }
-                    }
-
-                    // If no newline was found by 750, look for whitespace.
-                    if !found && end_pos < chars.len() {
-                        for i in end_pos..std::cmp::min(current_pos + 750, chars.len()) {
-                            if chars[i].is_whitespace() {
-                                end_pos = i;
-                                break;
-                            }
-                        }
-                    }
+    render_in_parallel(
+        &pending,
+        settings,
+        &detector,
+        render,
+        |file, (output, document)| {
+            run.record(&file.path, file.oid, vec![output]);
+            if let Some(document) = document {
+                for writer in writers.iter_mut() {
+                    writer.write(&document)?;
                 }
             }
-
-            let chunk: String = chars[current_pos..end_pos].into_iter().collect();
-            let code_uuid = generate_uuid();
-            split_content.push(format!("[UUID:{}]\n```\n{}\n```\n", code_uuid, chunk));
-
-            current_pos = end_pos;
-        }
-
-        let file_markdown = format!("{}\n{}", header, split_content.join("\n"));
-        let output_file_name = get_output_file_name(&relative_path);
-        let output_file_path = output_dir.join(&output_file_name);
-
-        fops::fops_write(&output_file_path, file_markdown)?;
-        Ok(())
-    })?;
+            Ok(())
+        },
+    )?;
+
+    println!("{}: {}.", output_dir.display(), run.finish()?);
+    for writer in writers {
+        let path = writer.path().display().to_string();
+        println!("{}: {} records.", path, writer.finish()?);
+    }
     Ok(())
 }
 
@@ -377,6 +716,12 @@ pub fn code_md_dataset_markdown(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::source::files::{directory_files, select_files};
+    use crate::source::git::Snapshot;
+    use crate::source::provider::open_source;
+    use crate::tools::config::FrontmatterFormat;
+    use crate::trans_md::languages::{Category, CategoryPolicy};
+    use tempfile::TempDir;
 
     // Test if the markdown tag is created correctly for given file extension.
     #[test]
@@ -384,15 +729,262 @@ mod tests {
         // Mock data or setup
         let file_extension = "rs";
 
-        let asset_path = get_asset_path();
-        println!("Asset path from test: {}", asset_path.display());
-
         // Invoke your function
-        let language_tag = md_lang_maps(file_extension).unwrap();
+        let language_tags = LanguageDetector::load()
+            .unwrap()
+            .detect(&format!("main.{}", file_extension), "");
 
         // Assert the expected outcome
-        assert_eq!(language_tag, "Rust");
+        assert_eq!(language_tags, ["Rust"]);
     }
 
-    // ... more tests as needed ...
+    #[test]
+    fn test_chunks_record_line_and_byte_ranges() {
+        let sizes = ChunkSettings {
+            min: 4,
+            target: 6,
+            max: 8,
+        };
+        let chunks = split_into_chunks("aé\nbb\ncccc\ndd", &sizes);
+        let ranges: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.start_line, c.end_line, c.start_byte, c.end_byte))
+            .collect();
+
+        assert_eq!(ranges, vec![(1, 2, 0, 7), (3, 3, 7, 12), (4, 4, 12, 14)]);
+        assert_eq!(chunks[1].content, "cccc\n");
+    }
+
+    #[test]
+    fn test_empty_file_spans_its_first_line() {
+        let metadata = SourceMetadata {
+            name: "repo".to_string(),
+            remote_url: None,
+            snapshot: None,
+            contributors: HashMap::new(),
+            latest_release: String::new(),
+            release_date: String::new(),
+        };
+        let settings = Settings::default();
+        let file = LoadedFile {
+            path: PathBuf::from("__init__.py"),
+            oid: Oid::hash_object(git2::ObjectType::Blob, b"").unwrap(),
+            file_name: "__init__.py".to_string(),
+            content: String::new(),
+        };
+        let detection = LanguageDetector::load()
+            .unwrap()
+            .classify(&file.file_name, &file.content);
+        let document = DocumentBuilder::new(metadata, &settings).build(&file, &detection, false);
+
+        let section = &document.sections[0];
+        assert_eq!((section.start_line, section.end_line), (1, 1));
+        assert_eq!((section.start_byte, section.end_byte), (0, 0));
+    }
+
+    #[test]
+    fn test_content_ids_are_stable_across_remotes() {
+        let metadata = |remote: &str| SourceMetadata {
+            name: "repo".to_string(),
+            remote_url: Some(remote.to_string()),
+            snapshot: None,
+            contributors: HashMap::new(),
+            latest_release: String::new(),
+            release_date: String::new(),
+        };
+        let oid = |content: &str| Oid::hash_object(git2::ObjectType::Blob, content.as_bytes());
+        let path = Path::new("src/lib.rs");
+
+        let ssh = IdGenerator::new(&metadata("git@github.com:org/repo.git"), IdMode::Content);
+        let https = IdGenerator::new(&metadata("https://github.com/org/repo"), IdMode::Content);
+        let file_id = ssh.file_id(path, oid("fn a() {}").unwrap());
+        assert_eq!(file_id, https.file_id(path, oid("fn a() {}").unwrap()));
+        assert_ne!(file_id, ssh.file_id(path, oid("fn b() {}").unwrap()));
+
+        let chunk = split_into_chunks("fn a() {}", &ChunkSettings::default()).remove(0);
+        assert_eq!(
+            ssh.chunk_id(&file_id, &chunk),
+            https.chunk_id(&file_id, &chunk)
+        );
+    }
+
+    #[test]
+    fn test_header_escapes_values_and_follows_field_order() {
+        let detection = Detection {
+            languages: vec!["C#".to_string()],
+            category: None,
+        };
+        let header = |format: FrontmatterFormat, fields: Option<&[String]>| {
+            create_file_metadata(
+                "repo: \"quoted\"",
+                "- dash.cs",
+                "2024-01-01 00:00:00",
+                &detection,
+                "https://example.com/a#b",
+                "",
+                "O'Brien (3) | #1 (2)",
+                "",
+                "",
+                Path::new("dir/- dash.cs"),
+                12,
+                "uuid",
+            )
+            .to_frontmatter(format, fields)
+            .unwrap()
+        };
+
+        let full = header(FrontmatterFormat::Yaml, None);
+        let yaml = full.trim_start_matches("---\n").trim_end_matches("---\n");
+        let metadata: FileMetadata = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(metadata.title, "repo: \"quoted\" - - dash.cs");
+        assert_eq!(metadata.tags, ["C#"]);
+        assert_eq!(metadata.contributors, "O'Brien (3) | #1 (2)");
+        assert_eq!(metadata.file_path, "dir/- dash.cs");
+
+        let keys: Vec<_> = serde_yaml::from_str::<serde_yaml::Mapping>(yaml)
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(keys, HEADER_FIELDS);
+
+        let fields = ["size".to_string(), "title".to_string()];
+        let selected = header(FrontmatterFormat::Yaml, Some(&fields));
+        assert_eq!(
+            selected,
+            "---\ntitle: 'repo: \"quoted\" - - dash.cs'\nsize: 12\n---\n"
+        );
+        assert_eq!(
+            header(FrontmatterFormat::Toml, Some(&fields)),
+            "+++\ntitle = 'repo: \"quoted\" - - dash.cs'\nsize = 12\n+++\n"
+        );
+    }
+
+    #[test]
+    fn test_restamp_matches_rendering_for_the_new_snapshot() {
+        let temp = TempDir::new().unwrap();
+        let metadata = |commit: &str| SourceMetadata {
+            name: "repo".to_string(),
+            remote_url: Some("https://github.com/org/repo".to_string()),
+            snapshot: Some(Snapshot {
+                commit: Oid::hash_object(git2::ObjectType::Commit, commit.as_bytes()).unwrap(),
+                label: "main".to_string(),
+            }),
+            contributors: HashMap::new(),
+            latest_release: commit.to_string(),
+            release_date: String::new(),
+        };
+        let settings = Settings {
+            chunk: ChunkSettings {
+                min: 4,
+                target: 6,
+                max: 8,
+            },
+            ids: IdMode::Content,
+            ..Default::default()
+        };
+        let file = LoadedFile {
+            path: PathBuf::from("src/a.rs"),
+            oid: Oid::hash_object(git2::ObjectType::Blob, b"fn a() {}\nfn b() {}\n").unwrap(),
+            file_name: "a.rs".to_string(),
+            content: "fn a() {}\nfn b() {}\n".to_string(),
+        };
+        let detection = LanguageDetector::load()
+            .unwrap()
+            .classify(&file.file_name, &file.content);
+        let old = DocumentBuilder::new(metadata("one"), &settings);
+        let new = DocumentBuilder::new(metadata("two"), &settings);
+
+        for chunked in [false, true] {
+            let render = |builder: &DocumentBuilder| {
+                let document = builder.build(&file, &detection, chunked);
+                match chunked {
+                    true => render::dataset_markdown(&document, settings.frontmatter, None),
+                    false => render::file_markdown(&document, settings.frontmatter, None),
+                }
+                .unwrap()
+            };
+            let output = temp.path().join(format!("{}.md", chunked));
+            fs::write(&output, render(&old)).unwrap();
+
+            new.restamp(&file.path, &output, chunked).unwrap();
+            assert_eq!(fs::read_to_string(&output).unwrap(), render(&new));
+        }
+    }
+
+    #[test]
+    fn test_parallel_rendering_keeps_file_order() {
+        let root = TempDir::new().unwrap();
+        // Enough files for several batches, with every third one in a filtered category.
+        let names: Vec<String> = (0..BATCH_SIZE * 2 + 10)
+            .map(|i| match i % 3 {
+                0 => format!("{:04}.json", i),
+                _ => format!("{:04}.rs", i),
+            })
+            .collect();
+        for name in &names {
+            fs::write(root.path().join(name), format!("// {}\n", name)).unwrap();
+        }
+
+        let settings = Settings {
+            workers: Some(4),
+            categories: CategoryPolicy {
+                exclude: vec![Category::Data],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let files = select_files(directory_files(root.path()).unwrap(), &settings).unwrap();
+        let detector = LanguageDetector::load().unwrap();
+        let files = filter_categories(files, &settings, &detector).unwrap();
+        let mut rendered = Vec::new();
+        render_in_parallel(
+            &files.iter().collect::<Vec<_>>(),
+            &settings,
+            &detector,
+            |file, _| Ok(file.content.clone()),
+            |file, content| {
+                assert_eq!(content, format!("// {}\n", file.file_name));
+                rendered.push(file.file_name.clone());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        let expected: Vec<&String> = names.iter().filter(|n| n.ends_with(".rs")).collect();
+        assert_eq!(rendered.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_excluded_categories_are_dropped_from_the_manifest() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("proj");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+        fs::write(root.join("b.json"), "{}\n").unwrap();
+        let source = open_source(root.to_str().unwrap(), &Settings::default()).unwrap();
+        let output_dir = temp.path().join("out");
+        let outputs = || {
+            let manifest =
+                fs::read_to_string(output_dir.join("multi/proj/.goblin-manifest.json")).unwrap();
+            (
+                manifest.contains("a.rs"),
+                manifest.contains("b.json"),
+                output_dir.join("multi/proj/b.json.md").exists(),
+            )
+        };
+
+        code_md_multi_markdown(source.as_ref(), &output_dir, &Settings::default()).unwrap();
+        assert_eq!(outputs(), (true, true, true));
+
+        let settings = Settings {
+            categories: CategoryPolicy {
+                exclude: vec![Category::Data],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        code_md_multi_markdown(source.as_ref(), &output_dir, &settings).unwrap();
+        assert_eq!(outputs(), (true, false, false));
+    }
 }
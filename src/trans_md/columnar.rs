@@ -0,0 +1,297 @@
+// trans_md/columnar.rs
+
+use arrow_array::builder::{StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{self, GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::tools::config::{Compression, Settings};
+use crate::tools::errors::CustomError;
+use crate::trans_md::document::{Document, Section};
+use crate::trans_md::languages::is_unknown;
+use crate::trans_md::render::{create_output, DocumentWriter};
+
+// Columns of the chunk table, one row per dataset section.
+fn chunk_schema() -> SchemaRef {
+    let text = |name: &str| Field::new(name, DataType::Utf8, false);
+    let count = |name: &str| Field::new(name, DataType::UInt64, false);
+    Arc::new(Schema::new(vec![
+        text("repo"),
+        text("path"),
+        Field::new("language", DataType::Utf8, true),
+        text("file_uuid"),
+        text("chunk_uuid"),
+        Field::new("chunk_index", DataType::UInt32, false),
+        count("start_line"),
+        count("end_line"),
+        text("content"),
+        count("size"),
+        text("release"),
+        text("commit"),
+    ]))
+}
+
+fn parquet_codec(compression: Compression) -> basic::Compression {
+    match compression {
+        Compression::Uncompressed => basic::Compression::UNCOMPRESSED,
+        Compression::Snappy => basic::Compression::SNAPPY,
+        Compression::Gzip => basic::Compression::GZIP(GzipLevel::default()),
+        Compression::Zstd => basic::Compression::ZSTD(ZstdLevel::default()),
+    }
+}
+
+/// Rows of the chunk table collected until a full row group can be written.
+#[derive(Default)]
+struct ChunkColumns {
+    repo: StringBuilder,
+    path: StringBuilder,
+    language: StringBuilder,
+    file_uuid: StringBuilder,
+    chunk_uuid: StringBuilder,
+    chunk_index: UInt32Builder,
+    start_line: UInt64Builder,
+    end_line: UInt64Builder,
+    content: StringBuilder,
+    size: UInt64Builder,
+    release: StringBuilder,
+    commit: StringBuilder,
+    rows: usize,
+}
+
+impl ChunkColumns {
+    fn push(&mut self, repo: &str, document: &Document, index: usize, section: &Section) {
+        let metadata = &document.file_metadata;
+        // Only a settled, known language is written; ambiguous and unknown files get null.
+        let language = match &metadata.tags[..] {
+            [language] if !is_unknown(language) => Some(language),
+            _ => None,
+        };
+
+        self.repo.append_value(repo);
+        self.path.append_value(&metadata.file_path);
+        self.language.append_option(language);
+        self.file_uuid.append_value(&metadata.uuid);
+        self.chunk_uuid.append_value(&section.uuid);
+        self.chunk_index.append_value(index as u32);
+        self.start_line.append_value(section.start_line as u64);
+        self.end_line.append_value(section.end_line as u64);
+        self.content.append_value(&section.content);
+        self.size.append_value(metadata.size as u64);
+        self.release.append_value(&metadata.latest_release);
+        self.commit.append_value(&metadata.commit);
+        self.rows += 1;
+    }
+
+    // Take the collected rows as a record batch, leaving the columns empty.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch, CustomError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.repo.finish()),
+            Arc::new(self.path.finish()),
+            Arc::new(self.language.finish()),
+            Arc::new(self.file_uuid.finish()),
+            Arc::new(self.chunk_uuid.finish()),
+            Arc::new(self.chunk_index.finish()),
+            Arc::new(self.start_line.finish()),
+            Arc::new(self.end_line.finish()),
+            Arc::new(self.content.finish()),
+            Arc::new(self.size.finish()),
+            Arc::new(self.release.finish()),
+            Arc::new(self.commit.finish()),
+        ];
+        self.rows = 0;
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+/// File format the chunk table is written in.
+trait BatchSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), CustomError>;
+
+    fn close(self) -> Result<(), CustomError>;
+}
+
+impl BatchSink for ArrowWriter<BufWriter<File>> {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), CustomError> {
+        Ok(self.write(batch)?)
+    }
+
+    fn close(self) -> Result<(), CustomError> {
+        ArrowWriter::close(self)?;
+        Ok(())
+    }
+}
+
+impl BatchSink for FileWriter<BufWriter<File>> {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), CustomError> {
+        Ok(self.write(batch)?)
+    }
+
+    fn close(mut self) -> Result<(), CustomError> {
+        Ok(self.finish()?)
+    }
+}
+
+/// Streams dataset sections into a columnar file, one row per chunk, writing a row group
+/// whenever `row_group_size` rows have been collected.
+struct ChunkTableWriter<S> {
+    path: PathBuf,
+    repo: String,
+    schema: SchemaRef,
+    row_group_size: usize,
+    columns: ChunkColumns,
+    sink: S,
+    written: usize,
+}
+
+impl<S: BatchSink> ChunkTableWriter<S> {
+    fn flush(&mut self) -> Result<(), CustomError> {
+        if self.columns.rows > 0 {
+            self.written += self.columns.rows;
+            let batch = self.columns.finish(&self.schema)?;
+            self.sink.write_batch(&batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: BatchSink> DocumentWriter for ChunkTableWriter<S> {
+    fn write(&mut self, document: &Document) -> Result<(), CustomError> {
+        for (index, section) in document.sections.iter().enumerate() {
+            self.columns.push(&self.repo, document, index, section);
+            if self.columns.rows == self.row_group_size {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<usize, CustomError> {
+        self.flush()?;
+        let ChunkTableWriter { sink, written, .. } = *self;
+        sink.close()?;
+        Ok(written)
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn chunk_table_writer<S: BatchSink + 'static>(
+    path: &Path,
+    repo: &str,
+    schema: SchemaRef,
+    settings: &Settings,
+    sink: S,
+) -> Box<dyn DocumentWriter> {
+    Box::new(ChunkTableWriter {
+        path: path.to_path_buf(),
+        repo: repo.to_string(),
+        schema,
+        row_group_size: settings.row_group_size,
+        columns: ChunkColumns::default(),
+        sink,
+        written: 0,
+    })
+}
+
+// Parquet file of the dataset's chunks, compressed with the configured codec.
+pub fn parquet_writer(
+    path: &Path,
+    repo: &str,
+    settings: &Settings,
+) -> Result<Box<dyn DocumentWriter>, CustomError> {
+    let schema = chunk_schema();
+    let properties = WriterProperties::builder()
+        .set_compression(parquet_codec(settings.compression))
+        .set_max_row_group_size(settings.row_group_size)
+        .build();
+    let sink = ArrowWriter::try_new(create_output(path)?, schema.clone(), Some(properties))?;
+    Ok(chunk_table_writer(path, repo, schema, settings, sink))
+}
+
+// Arrow IPC file of the dataset's chunks, uncompressed so it can be memory-mapped.
+pub fn arrow_writer(
+    path: &Path,
+    repo: &str,
+    settings: &Settings,
+) -> Result<Box<dyn DocumentWriter>, CustomError> {
+    let schema = chunk_schema();
+    let sink = FileWriter::try_new(create_output(path)?, &schema)?;
+    Ok(chunk_table_writer(path, repo, schema, settings, sink))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trans_md::document::FileMetadata;
+    use arrow_array::{Array, StringArray};
+    use arrow_ipc::reader::FileReader;
+    use parquet::file::reader::{FileReader as _, SerializedFileReader};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_chunks_are_written_in_row_groups() {
+        let dir = TempDir::new().unwrap();
+        let settings = Settings {
+            row_group_size: 2,
+            ..Default::default()
+        };
+        let document = Document {
+            file_metadata: FileMetadata {
+                tags: vec!["Rust".to_string()],
+                category: "programming".to_string(),
+                file_path: "src/a.rs".to_string(),
+                ..Default::default()
+            },
+            sections: ["one", "two", "three"]
+                .map(|uuid| Section {
+                    uuid: uuid.to_string(),
+                    ..Default::default()
+                })
+                .to_vec(),
+            ..Default::default()
+        };
+
+        let parquet_path = dir.path().join("repo.parquet");
+        let mut writer = parquet_writer(&parquet_path, "repo", &settings).unwrap();
+        writer.write(&document).unwrap();
+        assert_eq!(writer.finish().unwrap(), 3);
+
+        let reader = SerializedFileReader::new(File::open(&parquet_path).unwrap()).unwrap();
+        let row_groups: Vec<i64> = (0..reader.num_row_groups())
+            .map(|i| reader.metadata().row_group(i).num_rows())
+            .collect();
+        assert_eq!(row_groups, vec![2, 1]);
+
+        let arrow_path = dir.path().join("repo.arrow");
+        let mut writer = arrow_writer(&arrow_path, "repo", &settings).unwrap();
+        writer.write(&document).unwrap();
+        writer.finish().unwrap();
+
+        let batches: Vec<RecordBatch> = FileReader::try_new(File::open(&arrow_path).unwrap(), None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(batches.len(), 2);
+        let column = |name: &str| {
+            let array = batches[1].column_by_name(name).unwrap();
+            array
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(0)
+                .to_string()
+        };
+        assert_eq!(column("chunk_uuid"), "three");
+        assert_eq!(column("language"), "Rust");
+        assert_eq!(column("repo"), "repo");
+    }
+}
@@ -0,0 +1,115 @@
+// trans_md/document.rs
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::{self, Mapping, Value};
+
+use crate::tools::config::FrontmatterFormat;
+use crate::tools::errors::CustomError;
+
+/// Frontmatter of a generated file, written by the generators and read back by `md_json`. Fields are
+/// declared in the order of `code_md::HEADER_FIELDS`, which is the order they are written in.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileMetadata {
+    pub title: String,
+    pub date: String,
+    pub tags: Vec<String>,
+    pub category: String,
+    pub uuid: String,
+    pub github_name: String,
+    pub github_url: String,
+    pub commit: String,
+    pub contributors: String,
+    pub latest_release: String,
+    pub release_date: String,
+    pub file_path: String,
+    pub size: usize,
+}
+
+impl FileMetadata {
+    // Frontmatter block, including its delimiters, with only the selected fields or every field
+    // for `None`. Values are quoted and escaped by the format's serializer.
+    pub fn to_frontmatter(
+        &self,
+        format: FrontmatterFormat,
+        fields: Option<&[String]>,
+    ) -> Result<String, CustomError> {
+        let mut mapping = match serde_yaml::to_value(self)? {
+            Value::Mapping(mapping) => mapping,
+            _ => Mapping::new(),
+        };
+        if let Some(fields) = fields {
+            mapping.retain(|key, _| key.as_str().is_some_and(|k| fields.iter().any(|f| f == k)));
+        }
+
+        let body = match format {
+            FrontmatterFormat::Yaml => serde_yaml::to_string(&mapping)?,
+            FrontmatterFormat::Toml => toml::to_string(&mapping)
+                .map_err(|e| CustomError::StrError(format!("Failed to write TOML: {}", e)))?,
+            FrontmatterFormat::Json => serde_json::to_string_pretty(&mapping)? + "\n",
+        };
+        let delimiter = format.delimiter();
+        Ok(format!("{}\n{}{}\n", delimiter, body, delimiter))
+    }
+
+    // Parse the text between the delimiters of a frontmatter block. Errors carry the line within
+    // `text`, starting at 1, when the parser reports one.
+    pub fn from_frontmatter(
+        format: FrontmatterFormat,
+        text: &str,
+    ) -> Result<Self, (Option<usize>, String)> {
+        match format {
+            FrontmatterFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+                let line = e.location().map(|l| l.line());
+                (line, without_location(&e.to_string()))
+            }),
+            FrontmatterFormat::Toml => toml::from_str(text).map_err(|e| {
+                let line = e.span().map(|s| text[..s.start].matches('\n').count() + 1);
+                (line, e.message().to_string())
+            }),
+            FrontmatterFormat::Json => serde_json::from_str(text)
+                .map_err(|e| (Some(e.line()), without_location(&e.to_string()))),
+        }
+    }
+}
+
+// Drop the " at line L column C" suffix parsers add, since it counts from the frontmatter.
+fn without_location(message: &str) -> String {
+    message
+        .split(" at line ")
+        .next()
+        .unwrap_or(message)
+        .to_string()
+}
+
+/// A dataset chunk with its position in the source file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Section {
+    pub uuid: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub permalink: String,
+    pub content: String,
+}
+
+/// A source file as every generator sees it before rendering: its metadata and its content as
+/// sections. Dataset documents have one section per chunk, other documents a single section
+/// holding the whole file. Serializes as the dataset JSON record.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    pub file_metadata: FileMetadata,
+    pub sections: Vec<Section>,
+    /// Code fence info string for the file's language, empty when unknown.
+    #[serde(skip)]
+    pub fence_tag: String,
+}
+
+impl Document {
+    // The file's full content, put back together from its sections.
+    pub fn content(&self) -> String {
+        self.sections.iter().map(|s| s.content.as_str()).collect()
+    }
+}
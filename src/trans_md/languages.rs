@@ -0,0 +1,677 @@
+// trans_md/languages.rs
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::tools::config::Settings;
+use crate::tools::errors::CustomError;
+
+// Default language map, embedded so the binary works wherever it is installed.
+const DEFAULT_LANG_MAP: &str = include_str!("../assets/lang_maps.json");
+
+// Environment variable naming an override map, used when `language_map` is not configured.
+pub const LANG_MAP_ENV: &str = "GOBLIN_LANG_MAP";
+
+// Lines searched for vim and emacs modelines at each end of a file.
+const MODELINE_LINES: usize = 5;
+
+// Content markers that settle an ambiguous extension, as (extension, language, markers). The
+// first language that is a candidate and has a marker in the content wins. No markers means the
+// language is the default when nothing else matched.
+const HEURISTICS: &[(&str, &str, &[&str])] = &[
+    (
+        ".h",
+        "Objective-C",
+        &["@interface", "@implementation", "@protocol", "#import "],
+    ),
+    (
+        ".h",
+        "C++",
+        &[
+            "namespace ",
+            "template <",
+            "template<",
+            "std::",
+            "class ",
+            "public:",
+        ],
+    ),
+    (".h", "C", &[]),
+    (
+        ".m",
+        "Objective-C",
+        &["@interface", "@implementation", "@protocol", "#import "],
+    ),
+    (".m", "MATLAB", &["function ", "end\n", "disp("]),
+    (".pl", "Prolog", &[":- "]),
+    (
+        ".pl",
+        "Perl",
+        &["use strict", "use warnings", "my $", "sub "],
+    ),
+    (".inc", "PHP", &["<?php"]),
+];
+
+/// Broad kind of a language, emitted as `category` in headers and used to include or skip files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[value(rename_all = "lowercase")]
+pub enum Category {
+    Programming,
+    Markup,
+    Data,
+    Prose,
+    Config,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Programming => "programming",
+            Category::Markup => "markup",
+            Category::Data => "data",
+            Category::Prose => "prose",
+            Category::Config => "config",
+        }
+    }
+
+    // Category for a Linguist language type. Languages without a known type have no category.
+    fn from_kind(kind: Option<&str>) -> Option<Category> {
+        match kind {
+            Some("programming") => Some(Category::Programming),
+            Some("markup") => Some(Category::Markup),
+            Some("data") => Some(Category::Data),
+            Some("prose") => Some(Category::Prose),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Categories of files to process. Files of an unknown language only pass an exclude-only policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CategoryPolicy {
+    /// Only these categories when non-empty.
+    pub include: Vec<Category>,
+    pub exclude: Vec<Category>,
+}
+
+impl CategoryPolicy {
+    // Whether every file passes, so files need not be classified to apply the policy.
+    pub fn allows_all(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn allows(&self, category: Option<Category>) -> bool {
+        match category {
+            Some(category) => {
+                (self.include.is_empty() || self.include.contains(&category))
+                    && !self.exclude.contains(&category)
+            }
+            None => self.include.is_empty(),
+        }
+    }
+}
+
+/// A language in the map: either a plain list of extensions or this object.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageEntry {
+    /// Linguist's language type: programming, markup, data or prose.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Category overriding the one implied by `type`, e.g. `config` for INI files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+    /// Exact file names, e.g. `Makefile` or `.bashrc`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filenames: Vec<String>,
+    /// Shebang interpreters, e.g. `python3`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub interpreters: Vec<String>,
+    /// Other names, matched against vim and emacs modelines.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum MapEntry {
+    Extensions(Vec<String>),
+    Entry(LanguageEntry),
+}
+
+impl From<&LanguageEntry> for MapEntry {
+    // Entries with nothing but extensions keep the compact list form.
+    fn from(entry: &LanguageEntry) -> Self {
+        let extensions_only = LanguageEntry {
+            extensions: entry.extensions.clone(),
+            ..Default::default()
+        };
+        if *entry == extensions_only {
+            MapEntry::Extensions(entry.extensions.clone())
+        } else {
+            MapEntry::Entry(entry.clone())
+        }
+    }
+}
+
+impl LanguageEntry {
+    pub fn category(&self) -> Option<Category> {
+        self.category
+            .or_else(|| Category::from_kind(self.kind.as_deref()))
+    }
+}
+
+impl From<MapEntry> for LanguageEntry {
+    fn from(entry: MapEntry) -> Self {
+        match entry {
+            MapEntry::Extensions(extensions) => LanguageEntry {
+                extensions,
+                ..Default::default()
+            },
+            MapEntry::Entry(entry) => entry,
+        }
+    }
+}
+
+/// Detects file languages from the language map, parsed once and shared by a generator run.
+///
+/// Detection tries, in order: the exact file name, the shebang interpreter, a vim or emacs
+/// modeline, then the extension, using content heuristics to settle ambiguous extensions.
+#[derive(Debug, Clone, Default)]
+pub struct LanguageDetector {
+    /// Lowercase extension, with its leading dot, to every language using it in name order.
+    by_extension: HashMap<String, Vec<String>>,
+    by_filename: HashMap<String, Vec<String>>,
+    by_interpreter: HashMap<String, Vec<String>>,
+    /// Lowercase language names and aliases.
+    by_alias: HashMap<String, Vec<String>>,
+    categories: HashMap<String, Category>,
+}
+
+/// Languages detected for a file and the category of the first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub languages: Vec<String>,
+    /// `None` when the file's language is unknown.
+    pub category: Option<Category>,
+}
+
+impl Detection {
+    // Code fence info string for the language, its name lowercased with spaces as dashes the
+    // way GitHub matches it. Empty when the language is unknown or could not be settled.
+    pub fn fence_tag(&self) -> String {
+        match &self.languages[..] {
+            [language] if !is_unknown(language) => language.to_lowercase().replace(' ', "-"),
+            _ => String::new(),
+        }
+    }
+}
+
+impl LanguageDetector {
+    // The embedded default map.
+    pub fn load() -> Result<Self, CustomError> {
+        Self::from_json(DEFAULT_LANG_MAP, "assets/lang_maps.json")
+    }
+
+    // The default map with the override from `language_map` or `$GOBLIN_LANG_MAP` merged on top.
+    pub fn for_settings(settings: &Settings) -> Result<Self, CustomError> {
+        let mut detector = Self::load()?;
+        let override_path = settings
+            .language_map
+            .clone()
+            .or_else(|| env::var_os(LANG_MAP_ENV).map(Into::into));
+
+        if let Some(path) = override_path {
+            detector.merge_override(read_map(&path)?);
+        }
+        Ok(detector)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, CustomError> {
+        Ok(Self::from_map(&read_map(path)?))
+    }
+
+    pub fn from_json(content: &str, origin: &str) -> Result<Self, CustomError> {
+        Ok(Self::from_map(&parse_map(content, origin)?))
+    }
+
+    pub fn from_map(languages: &BTreeMap<String, LanguageEntry>) -> Self {
+        let mut detector = LanguageDetector::default();
+        for (language, entry) in languages {
+            for extension in &entry.extensions {
+                index(
+                    &mut detector.by_extension,
+                    normalize_extension(extension),
+                    language,
+                );
+            }
+            for filename in &entry.filenames {
+                index(&mut detector.by_filename, filename.clone(), language);
+            }
+            for interpreter in &entry.interpreters {
+                index(&mut detector.by_interpreter, interpreter.clone(), language);
+            }
+            for alias in entry.aliases.iter().chain([language]) {
+                index(&mut detector.by_alias, alias.to_ascii_lowercase(), language);
+            }
+            if let Some(category) = entry.category() {
+                detector.categories.insert(language.clone(), category);
+            }
+        }
+        detector
+    }
+
+    // Overlay another map. Keys it lists resolve only to its languages.
+    pub fn merge(&mut self, overrides: LanguageDetector) {
+        self.by_extension.extend(overrides.by_extension);
+        self.by_filename.extend(overrides.by_filename);
+        self.by_interpreter.extend(overrides.by_interpreter);
+        self.by_alias.extend(overrides.by_alias);
+        self.categories.extend(overrides.categories);
+    }
+
+    // Overlay a user's language map. Languages it adds without a `category` or `type`, such as
+    // plain extension lists, are taken as programming languages; languages already in the map
+    // keep their category.
+    pub fn merge_override(&mut self, mut languages: BTreeMap<String, LanguageEntry>) {
+        for (language, entry) in &mut languages {
+            if entry.category().is_none() && self.category(language).is_none() {
+                entry.category = Some(Category::Programming);
+            }
+        }
+        self.merge(Self::from_map(&languages));
+    }
+
+    // The merged map as stable text, so a run can tell whether detection may have changed.
+    pub fn fingerprint(&self) -> String {
+        let sorted = |map: &HashMap<String, Vec<String>>| format!("{:?}", BTreeMap::from_iter(map));
+        format!(
+            "{}|{}|{}|{}|{:?}",
+            sorted(&self.by_extension),
+            sorted(&self.by_filename),
+            sorted(&self.by_interpreter),
+            sorted(&self.by_alias),
+            BTreeMap::from_iter(&self.categories),
+        )
+    }
+
+    // Category of a language by name, `None` for names not in the map.
+    pub fn category(&self, language: &str) -> Option<Category> {
+        self.categories.get(language).copied()
+    }
+
+    // Languages of a file along with their category.
+    pub fn classify(&self, file_name: &str, content: &str) -> Detection {
+        let languages = self.detect(file_name, content);
+        let category = languages.first().and_then(|l| self.category(l));
+        Detection {
+            languages,
+            category,
+        }
+    }
+
+    // Every language using the extension, in name order. Empty when the extension is unknown.
+    pub fn candidates(&self, file_extension: &str) -> &[String] {
+        lookup(&self.by_extension, &normalize_extension(file_extension))
+    }
+
+    // Languages of a file, falling back to its dotted extension when nothing matches. More than
+    // one language is returned when an ambiguous extension could not be settled.
+    pub fn detect(&self, file_name: &str, content: &str) -> Vec<String> {
+        let by_name = lookup(&self.by_filename, file_name);
+        if !by_name.is_empty() {
+            return by_name.to_vec();
+        }
+
+        let by_interpreter = shebang_interpreter(content).map_or(&[][..], |interpreter| {
+            self.interpreter_candidates(&interpreter)
+        });
+        if !by_interpreter.is_empty() {
+            return by_interpreter.to_vec();
+        }
+
+        let by_modeline = modeline(content).map_or(&[][..], |mode| lookup(&self.by_alias, &mode));
+        if !by_modeline.is_empty() {
+            return by_modeline.to_vec();
+        }
+
+        match self.extension_candidates(file_name) {
+            Some((_, [language])) => vec![language.clone()],
+            Some((extension, candidates)) => settle(&extension, candidates, content),
+            None => {
+                let extension = Path::new(file_name)
+                    .extension()
+                    .map_or(String::new(), |ext| ext.to_string_lossy().into_owned());
+                vec![format_extension(&extension)]
+            }
+        }
+    }
+
+    // Interpreters match exactly, then without a version suffix, so `python3.11` finds `python`.
+    fn interpreter_candidates(&self, interpreter: &str) -> &[String] {
+        match lookup(&self.by_interpreter, interpreter) {
+            [] => {
+                let unversioned =
+                    interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+                lookup(&self.by_interpreter, unversioned)
+            }
+            candidates => candidates,
+        }
+    }
+
+    // Candidates for the longest known extension of the name, so `.cmake.in` wins over `.in`.
+    fn extension_candidates(&self, file_name: &str) -> Option<(String, &[String])> {
+        file_name
+            .char_indices()
+            .filter(|&(i, c)| c == '.' && i > 0)
+            .map(|(i, _)| normalize_extension(&file_name[i..]))
+            .find_map(|extension| {
+                let candidates = lookup(&self.by_extension, &extension);
+                (!candidates.is_empty()).then_some((extension, candidates))
+            })
+    }
+}
+
+// The embedded default map, by language name.
+pub fn default_map() -> Result<BTreeMap<String, LanguageEntry>, CustomError> {
+    parse_map(DEFAULT_LANG_MAP, "assets/lang_maps.json")
+}
+
+// Parse a `{ "Language": [".ext", ...] }` map, where entries may also be `LanguageEntry`
+// objects. `origin` names the map in errors.
+fn read_map(path: &Path) -> Result<BTreeMap<String, LanguageEntry>, CustomError> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        CustomError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    parse_map(&content, &path.display().to_string())
+}
+
+pub fn parse_map(
+    content: &str,
+    origin: &str,
+) -> Result<BTreeMap<String, LanguageEntry>, CustomError> {
+    let languages: BTreeMap<String, MapEntry> = serde_json::from_str(content)
+        .map_err(|e| CustomError::DetailedJsonParsingError(origin.into(), e.to_string()))?;
+    Ok(languages
+        .into_iter()
+        .map(|(name, entry)| (name, entry.into()))
+        .collect())
+}
+
+// Serialize a map in the format of `assets/lang_maps.json`.
+pub fn map_to_json(languages: &BTreeMap<String, LanguageEntry>) -> Result<String, CustomError> {
+    let entries: BTreeMap<&String, MapEntry> = languages
+        .iter()
+        .map(|(name, entry)| (name, entry.into()))
+        .collect();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+// Whether a detected language is the dotted extension `detect` falls back to.
+pub fn is_unknown(language: &str) -> bool {
+    language.starts_with('.')
+}
+
+fn index(map: &mut HashMap<String, Vec<String>>, key: String, language: &str) {
+    let languages = map.entry(key).or_default();
+    if !languages.iter().any(|l| l == language) {
+        languages.push(language.to_string());
+    }
+}
+
+fn lookup<'a>(map: &'a HashMap<String, Vec<String>>, key: &str) -> &'a [String] {
+    map.get(key).map_or(&[], Vec::as_slice)
+}
+
+// Pick one language for an ambiguous extension from the content, or keep every candidate.
+fn settle(extension: &str, candidates: &[String], content: &str) -> Vec<String> {
+    HEURISTICS
+        .iter()
+        .filter(|(ext, language, _)| *ext == extension && candidates.iter().any(|c| c == language))
+        .find(|(_, _, markers)| markers.is_empty() || markers.iter().any(|m| content.contains(m)))
+        .map_or_else(
+            || candidates.to_vec(),
+            |(_, language, _)| vec![language.to_string()],
+        )
+}
+
+// Program named by a `#!` line, looking through `/usr/bin/env` and its options.
+fn shebang_interpreter(content: &str) -> Option<String> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    Some(program.to_string())
+}
+
+// Language named by a vim or emacs modeline near the start or end of the content.
+fn modeline(content: &str) -> Option<String> {
+    let lines = content.lines().collect::<Vec<_>>();
+    lines
+        .iter()
+        .take(MODELINE_LINES)
+        .chain(lines.iter().rev().take(MODELINE_LINES))
+        .find_map(|line| emacs_mode(line).or_else(|| vim_filetype(line)))
+}
+
+// `-*- mode: python -*-` or the short form `-*- python -*-`.
+fn emacs_mode(line: &str) -> Option<String> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (inner, _) = rest.split_once("-*-")?;
+    let mode = match inner
+        .split(';')
+        .find_map(|v| v.trim().strip_prefix("mode:"))
+    {
+        Some(mode) => mode,
+        None if !inner.contains(':') => inner,
+        None => return None,
+    };
+    let mode = mode.trim().to_ascii_lowercase();
+    (!mode.is_empty()).then_some(mode)
+}
+
+// `vim: set ft=python:` and the `vi:`, `ex:`, `filetype=` and `syntax=` variants.
+fn vim_filetype(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|marker| {
+            let i = line.find(marker)?;
+            let standalone = line[..i]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace);
+            standalone.then_some(i + marker.len())
+        })
+        .min()?;
+
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            ["ft=", "filetype=", "syntax="]
+                .iter()
+                .find_map(|key| option.strip_prefix(key))
+        })
+        .filter(|value| !value.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+fn format_extension(file_extension: &str) -> String {
+    if file_extension.starts_with('.') {
+        file_extension.to_string()
+    } else {
+        format!(".{}", file_extension)
+    }
+}
+
+fn normalize_extension(file_extension: &str) -> String {
+    format_extension(file_extension).to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ambiguous_extensions_return_all_candidates() {
+        let detector = LanguageDetector::from_json(
+            r#"{ "Objective-C": [".m", ".h"], "MATLAB": [".m"], "C": [".c", ".h"] }"#,
+            "test",
+        )
+        .unwrap();
+
+        assert_eq!(detector.candidates("m"), ["MATLAB", "Objective-C"]);
+        assert_eq!(detector.candidates(".H"), ["C", "Objective-C"]);
+        assert_eq!(detector.detect("a.c", ""), ["C"]);
+        assert_eq!(detector.detect("a.zzz", ""), [".zzz"]);
+    }
+
+    #[test]
+    fn test_override_map_takes_precedence() {
+        let mut detector = LanguageDetector::load().unwrap();
+        let overrides =
+            LanguageDetector::from_json(r#"{ "Scout DSL": [".gob", ".rs"] }"#, "test").unwrap();
+        detector.merge(overrides);
+
+        assert_eq!(detector.candidates("gob"), ["Scout DSL"]);
+        assert_eq!(detector.candidates("rs"), ["Scout DSL"]);
+        assert_eq!(detector.candidates("json"), ["JSON"]);
+    }
+
+    #[test]
+    fn test_map_round_trips_the_default_asset() {
+        assert_eq!(
+            map_to_json(&default_map().unwrap()).unwrap(),
+            DEFAULT_LANG_MAP
+        );
+    }
+
+    #[test]
+    fn test_detection_chain() {
+        let detector = LanguageDetector::load().unwrap();
+
+        assert_eq!(detector.detect("Makefile", ""), ["Makefile"]);
+        assert_eq!(detector.detect("CMakeLists.txt", ""), ["CMake"]);
+        assert_eq!(detector.detect(".bashrc", ""), ["Shell"]);
+        assert_eq!(
+            detector.detect("build", "#!/usr/bin/env python3\n"),
+            ["Python"]
+        );
+        assert_eq!(detector.detect("run", "#!/bin/bash -e\n"), ["Shell"]);
+        assert_eq!(detector.detect("notes", "# vim: set ft=ruby:\n"), ["Ruby"]);
+        assert_eq!(detector.detect("x", "; -*- mode: Perl -*-\n"), ["Perl"]);
+        assert_eq!(detector.detect("a.h", "int f(void);\n"), ["C"]);
+        assert_eq!(detector.detect("a.h", "namespace x {}\n"), ["C++"]);
+        assert_eq!(
+            detector.detect("a.h", "@interface Foo\n@end\n"),
+            ["Objective-C"]
+        );
+        assert_eq!(detector.detect("noext", "plain\n"), ["."]);
+    }
+
+    #[test]
+    fn test_every_embedded_language_has_a_category() {
+        let map = default_map().unwrap();
+        let missing: Vec<&String> = map
+            .iter()
+            .filter(|(_, entry)| entry.category().is_none())
+            .map(|(name, _)| name)
+            .collect();
+        assert!(missing.is_empty(), "no category for {:?}", missing);
+
+        let detector = LanguageDetector::load().unwrap();
+        for file_name in ["a.jsonld", "a.vtt", "a.srt", "a.cson", "a.obj"] {
+            assert_eq!(
+                detector.classify(file_name, "").category,
+                Some(Category::Data),
+                "{}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_categories_and_policy() {
+        let detector = LanguageDetector::from_json(
+            r#"{
+                "Rust": { "type": "programming", "extensions": [".rs"] },
+                "Untyped": [".untyped"],
+                "Markdown": { "type": "prose", "extensions": [".md"] },
+                "INI": { "type": "data", "category": "config", "extensions": [".ini"] }
+            }"#,
+            "test",
+        )
+        .unwrap();
+
+        assert_eq!(
+            detector.classify("main.rs", "").category,
+            Some(Category::Programming)
+        );
+        assert_eq!(
+            detector.classify("README.md", "").category,
+            Some(Category::Prose)
+        );
+        assert_eq!(
+            detector.classify("setup.ini", "").category,
+            Some(Category::Config)
+        );
+        assert_eq!(detector.classify("a.untyped", "").category, None);
+        assert_eq!(detector.classify("a.zzz", "").category, None);
+
+        let only_programming = CategoryPolicy {
+            include: vec![Category::Programming],
+            ..Default::default()
+        };
+        assert!(only_programming.allows(Some(Category::Programming)));
+        assert!(!only_programming.allows(Some(Category::Prose)));
+        assert!(!only_programming.allows(None));
+
+        let no_data = CategoryPolicy {
+            exclude: vec![Category::Data, Category::Config],
+            ..Default::default()
+        };
+        assert!(no_data.allows(Some(Category::Markup)));
+        assert!(!no_data.allows(Some(Category::Config)));
+        assert!(no_data.allows(None));
+    }
+
+    #[test]
+    fn test_override_languages_default_to_programming() {
+        let mut detector = LanguageDetector::load().unwrap();
+        let overrides = parse_map(
+            r#"{
+                "Scout DSL": [".gob"],
+                "Scout Settings": { "type": "data", "extensions": [".scoutrc"] },
+                "Markdown": [".mdx2"]
+            }"#,
+            "test",
+        )
+        .unwrap();
+        detector.merge_override(overrides);
+
+        let category = |file_name| detector.classify(file_name, "").category;
+        assert_eq!(category("a.gob"), Some(Category::Programming));
+        assert_eq!(category("a.scoutrc"), Some(Category::Data));
+        assert_eq!(category("a.mdx2"), Some(Category::Prose));
+        assert!(CategoryPolicy {
+            include: vec![Category::Programming],
+            ..Default::default()
+        }
+        .allows(category("a.gob")));
+    }
+}
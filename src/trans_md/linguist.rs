@@ -0,0 +1,223 @@
+// trans_md/linguist.rs
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::tools::errors::CustomError;
+use crate::trans_md::languages::{Category, LanguageEntry};
+
+/// A language in GitHub Linguist's `languages.yml`. Keys not used by the map are ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LinguistLanguage {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    extensions: Vec<String>,
+    filenames: Vec<String>,
+    interpreters: Vec<String>,
+    aliases: Vec<String>,
+}
+
+impl From<LinguistLanguage> for LanguageEntry {
+    fn from(language: LinguistLanguage) -> Self {
+        LanguageEntry {
+            kind: language.kind,
+            category: None,
+            extensions: language.extensions,
+            filenames: language.filenames,
+            interpreters: language.interpreters,
+            aliases: language.aliases,
+        }
+    }
+}
+
+// Convert the content of Linguist's `languages.yml` into the language map format.
+pub fn import_linguist(
+    yaml: &str,
+    origin: &str,
+) -> Result<BTreeMap<String, LanguageEntry>, CustomError> {
+    let languages: BTreeMap<String, LinguistLanguage> = serde_yaml::from_str(yaml)
+        .map_err(|e| CustomError::ConfigError(format!("{}: {}", origin, e)))?;
+    Ok(languages
+        .into_iter()
+        .map(|(name, language)| (name, language.into()))
+        .collect())
+}
+
+pub fn import_linguist_file(path: &Path) -> Result<BTreeMap<String, LanguageEntry>, CustomError> {
+    let yaml = fs::read_to_string(path)
+        .map_err(|e| CustomError::StrError(format!("Failed to read {}: {}", path.display(), e)))?;
+    import_linguist(&yaml, &path.display().to_string())
+}
+
+// Keep the categories set in the current map, which Linguist has no equivalent for.
+pub fn keep_categories(
+    current: &BTreeMap<String, LanguageEntry>,
+    imported: &mut BTreeMap<String, LanguageEntry>,
+) {
+    for (name, entry) in imported.iter_mut() {
+        if let Some(category) = current.get(name).and_then(|e| e.category) {
+            entry.category = Some(category);
+        }
+    }
+}
+
+/// Differences between two language maps, by language name.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MapChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Changed languages with a description of each changed field.
+    pub changed: Vec<(String, Vec<String>)>,
+    pub unchanged: usize,
+}
+
+impl MapChanges {
+    pub fn between(
+        current: &BTreeMap<String, LanguageEntry>,
+        imported: &BTreeMap<String, LanguageEntry>,
+    ) -> Self {
+        let mut changes = MapChanges {
+            removed: current
+                .keys()
+                .filter(|name| !imported.contains_key(*name))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+
+        for (name, new) in imported {
+            match current.get(name) {
+                None => changes.added.push(name.clone()),
+                Some(old) if old == new => changes.unchanged += 1,
+                Some(old) => changes
+                    .changed
+                    .push((name.clone(), field_changes(old, new))),
+            }
+        }
+        changes
+    }
+}
+
+impl fmt::Display for MapChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.added {
+            writeln!(f, "+ {}", name)?;
+        }
+        for name in &self.removed {
+            writeln!(f, "- {}", name)?;
+        }
+        for (name, fields) in &self.changed {
+            writeln!(f, "~ {}: {}", name, fields.join("; "))?;
+        }
+        writeln!(
+            f,
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        )
+    }
+}
+
+fn field_changes(old: &LanguageEntry, new: &LanguageEntry) -> Vec<String> {
+    let mut fields = Vec::new();
+    if old.kind != new.kind {
+        let show = |kind: &Option<String>| kind.clone().unwrap_or_else(|| "none".to_string());
+        fields.push(format!("type {} -> {}", show(&old.kind), show(&new.kind)));
+    }
+
+    if old.category != new.category {
+        let show = |category: Option<Category>| category.map_or("none", |c| c.as_str());
+        fields.push(format!(
+            "category {} -> {}",
+            show(old.category),
+            show(new.category)
+        ));
+    }
+
+    let lists = [
+        ("extensions", &old.extensions, &new.extensions),
+        ("filenames", &old.filenames, &new.filenames),
+        ("interpreters", &old.interpreters, &new.interpreters),
+        ("aliases", &old.aliases, &new.aliases),
+    ];
+    for (field, old, new) in lists {
+        let added = new
+            .iter()
+            .filter(|v| !old.contains(v))
+            .map(|v| format!("+{}", v));
+        let removed = old
+            .iter()
+            .filter(|v| !new.contains(v))
+            .map(|v| format!("-{}", v));
+        let diff = added.chain(removed).collect::<Vec<_>>();
+        if !diff.is_empty() {
+            fields.push(format!("{} {}", field, diff.join(" ")));
+        }
+    }
+
+    if fields.is_empty() {
+        fields.push("order".to_string());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_and_report_changes() {
+        let yaml = r##"
+Python:
+  type: programming
+  color: "#3572A5"
+  extensions:
+  - ".py"
+  - ".pyw"
+  interpreters:
+  - python3
+  aliases:
+  - python3
+  language_id: 303
+Zig:
+  type: programming
+  extensions:
+  - ".zig"
+"##;
+        let imported = import_linguist(yaml, "languages.yml").unwrap();
+        let error = import_linguist("Python: [", "languages.yml").unwrap_err();
+        assert!(matches!(error, CustomError::ConfigError(m) if m.starts_with("languages.yml: ")));
+        assert_eq!(imported["Python"].kind.as_deref(), Some("programming"));
+        assert_eq!(imported["Python"].interpreters, ["python3"]);
+
+        let current = BTreeMap::from([
+            (
+                "Python".to_string(),
+                LanguageEntry {
+                    extensions: vec![".py".to_string(), ".spec".to_string()],
+                    ..Default::default()
+                },
+            ),
+            ("Old".to_string(), LanguageEntry::default()),
+        ]);
+        let changes = MapChanges::between(&current, &imported);
+
+        assert_eq!(changes.added, ["Zig"]);
+        assert_eq!(changes.removed, ["Old"]);
+        assert_eq!(
+            changes.changed[0].1,
+            [
+                "type none -> programming",
+                "extensions +.pyw -.spec",
+                "interpreters +python3",
+                "aliases +python3"
+            ]
+        );
+    }
+}
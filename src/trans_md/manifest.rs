@@ -0,0 +1,453 @@
+// trans_md/manifest.rs
+
+use git2::{ObjectType, Oid};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::source::provider::SourceMetadata;
+use crate::tools::config::Settings;
+use crate::tools::errors::CustomError;
+use crate::tools::fops;
+use crate::trans_md::languages::LanguageDetector;
+
+pub const MANIFEST_FILE_NAME: &str = ".goblin-manifest.json";
+
+/// Outputs generated for one source file, keyed by its path in `Manifest::files`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Git blob OID of the content the outputs were generated from.
+    pub oid: String,
+    /// Output files, relative to the output directory.
+    pub outputs: Vec<PathBuf>,
+}
+
+/// Record of a previous run, written next to the generated files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Hash of the settings that shape the output. A change regenerates every file.
+    pub settings_hash: String,
+    /// Hash of the snapshot the headers were stamped with. A change rewrites only the headers.
+    #[serde(default)]
+    pub snapshot_hash: String,
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    // Read the manifest in `dir`. A missing or unreadable manifest means a full run. Entries with
+    // an output outside `dir` are dropped, so an edited manifest cannot have files elsewhere
+    // rewritten or removed; their source files are generated again.
+    fn load(dir: &Path) -> Option<Manifest> {
+        let content = fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).ok()?;
+        let mut manifest: Manifest = serde_json::from_str(&content).ok()?;
+        manifest
+            .files
+            .retain(|_, entry| entry.outputs.iter().all(|o| output_path(dir, o).is_some()));
+        Some(manifest)
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), CustomError> {
+        let json = serde_json::to_string_pretty(self)?;
+        fops::fops_write(&dir.join(MANIFEST_FILE_NAME), json)?;
+        Ok(())
+    }
+}
+
+// Hash the settings that change generated content, along with the generator, the crate version
+// and the language map as merged with any override.
+pub fn settings_hash(generator: &str, settings: &Settings, detector: &LanguageDetector) -> String {
+    let shaping = format!(
+        "{}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{:?}",
+        env!("CARGO_PKG_VERSION"),
+        generator,
+        settings.file_source,
+        settings.chunk,
+        settings.header_fields,
+        settings.frontmatter,
+        settings.host,
+        settings.url_template,
+        settings.ids,
+        detector.fingerprint(),
+        settings.categories,
+    );
+    hash(&shaping)
+}
+
+// Hash what headers and permalinks record about the source as a whole: the commit and its label,
+// the remote and the latest release. These change with every commit, so unchanged files only
+// have their headers rewritten.
+pub fn snapshot_hash(source: &SourceMetadata) -> String {
+    let snapshot = source
+        .snapshot
+        .as_ref()
+        .map(|snapshot| (&snapshot.label, snapshot.commit));
+    hash(&format!(
+        "{:?}|{:?}|{:?}|{:?}",
+        snapshot, source.remote_url, source.latest_release, source.release_date,
+    ))
+}
+
+fn hash(text: &str) -> String {
+    Oid::hash_object(ObjectType::Blob, text.as_bytes())
+        .map(|oid| oid.to_string())
+        .unwrap_or_default()
+}
+
+/// Counts of source files by what happened to them in a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} changed, {} removed, {} unchanged",
+            self.added, self.changed, self.removed, self.unchanged
+        )
+    }
+}
+
+/// Tracks one generation run against the previous manifest, so only new or changed files are
+/// regenerated and outputs of removed files are deleted.
+pub struct IncrementalRun {
+    output_dir: PathBuf,
+    previous: Manifest,
+    current: Manifest,
+    summary: RunSummary,
+    /// Whether kept outputs carry the headers of another snapshot.
+    stale_headers: bool,
+}
+
+impl IncrementalRun {
+    // Start a run in `output_dir`. The previous manifest is ignored when `incremental` is off or
+    // the settings changed, but its outputs are still cleaned up for removed files.
+    pub fn start(
+        output_dir: &Path,
+        settings_hash: String,
+        snapshot_hash: String,
+        incremental: bool,
+    ) -> Self {
+        let previous = Manifest::load(output_dir).unwrap_or_default();
+        let reuse = incremental && previous.settings_hash == settings_hash;
+
+        IncrementalRun {
+            output_dir: output_dir.to_path_buf(),
+            stale_headers: reuse && previous.snapshot_hash != snapshot_hash,
+            previous: if reuse {
+                previous
+            } else {
+                Manifest {
+                    files: previous.files,
+                    ..Default::default()
+                }
+            },
+            current: Manifest {
+                settings_hash,
+                snapshot_hash,
+                files: BTreeMap::new(),
+            },
+            summary: RunSummary::default(),
+        }
+    }
+
+    // Return true and keep the previous outputs when the file is unchanged since the last run.
+    pub fn keep(&mut self, path: &Path, oid: Oid) -> bool {
+        let key = manifest_key(path);
+        let unchanged = !self.previous.settings_hash.is_empty()
+            && self.previous.files.get(&key).is_some_and(|entry| {
+                entry.oid == oid.to_string()
+                    && entry
+                        .outputs
+                        .iter()
+                        .all(|o| self.output_dir.join(o).is_file())
+            });
+
+        if unchanged {
+            if let Some(entry) = self.previous.files.remove(&key) {
+                self.current.files.insert(key, entry);
+            }
+            self.summary.unchanged += 1;
+        }
+        unchanged
+    }
+
+    // Kept files whose headers were stamped for another snapshot, each with one of its outputs
+    // in the output directory. Empty when the snapshot is the same as in the previous run.
+    pub fn stale_outputs(&self) -> Vec<(PathBuf, PathBuf)> {
+        if !self.stale_headers {
+            return Vec::new();
+        }
+        self.current
+            .files
+            .iter()
+            .flat_map(|(key, entry)| {
+                entry
+                    .outputs
+                    .iter()
+                    .map(move |output| (PathBuf::from(key), self.output_dir.join(output)))
+            })
+            .collect()
+    }
+
+    // Stop keeping a file, so that it is regenerated and recorded as changed.
+    pub fn forget(&mut self, path: &Path) {
+        if let Some(entry) = self.current.files.remove(&manifest_key(path)) {
+            self.previous.files.insert(manifest_key(path), entry);
+            self.summary.unchanged -= 1;
+        }
+    }
+
+    // Record the outputs written for a new or changed file.
+    pub fn record(&mut self, path: &Path, oid: Oid, outputs: Vec<PathBuf>) {
+        let key = manifest_key(path);
+        match self.previous.files.remove(&key) {
+            Some(_) => self.summary.changed += 1,
+            None => self.summary.added += 1,
+        }
+
+        let outputs = outputs
+            .into_iter()
+            .map(|o| {
+                o.strip_prefix(&self.output_dir)
+                    .map_or(o.clone(), Path::to_path_buf)
+            })
+            .collect();
+        self.current.files.insert(
+            key,
+            ManifestEntry {
+                oid: oid.to_string(),
+                outputs,
+            },
+        );
+    }
+
+    // Delete outputs of files that are no longer in the source and save the new manifest.
+    pub fn finish(mut self) -> Result<RunSummary, CustomError> {
+        let still_written: Vec<&PathBuf> = self
+            .current
+            .files
+            .values()
+            .flat_map(|entry| &entry.outputs)
+            .collect();
+
+        for entry in self.previous.files.values() {
+            self.summary.removed += 1;
+            for output in entry.outputs.iter().filter(|o| !still_written.contains(o)) {
+                remove_output(&self.output_dir, output)?;
+            }
+        }
+
+        self.current.save(&self.output_dir)?;
+        Ok(self.summary)
+    }
+}
+
+fn manifest_key(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Path of an output in the output directory. `None` for outputs that are absolute or have `..`
+// components, which could point outside it.
+fn output_path(output_dir: &Path, output: &Path) -> Option<PathBuf> {
+    let relative = output
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    let path = output_dir.join(output);
+    (relative && path.starts_with(output_dir)).then_some(path)
+}
+
+// Remove an output file and any directories it leaves empty below the output directory.
+fn remove_output(output_dir: &Path, output: &Path) -> Result<(), CustomError> {
+    let path = output_path(output_dir, output).ok_or_else(|| {
+        CustomError::StrError(format!(
+            "Refusing to remove {}, which is outside {}",
+            output.display(),
+            output_dir.display()
+        ))
+    })?;
+    if path.is_file() {
+        fs::remove_file(&path)?;
+    }
+
+    let mut dir = path.parent();
+    while let Some(current) = dir.filter(|d| *d != output_dir && d.starts_with(output_dir)) {
+        if fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::git::Snapshot;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn oid(content: &str) -> Oid {
+        Oid::hash_object(ObjectType::Blob, content.as_bytes()).unwrap()
+    }
+
+    // Record `files` as written in `dir`, creating their outputs.
+    fn record(run: &mut IncrementalRun, dir: &Path, files: &[(&str, &str)]) {
+        for (path, content) in files {
+            let output = dir.join(format!("{}.md", path));
+            fops::fops_write(&output, content.to_string()).unwrap();
+            run.record(Path::new(path), oid(content), vec![output]);
+        }
+    }
+
+    #[test]
+    fn test_unchanged_files_are_kept_and_removed_files_cleaned_up() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        let mut run = IncrementalRun::start(dir, "hash".to_string(), "one".to_string(), true);
+        assert!(!run.keep(Path::new("a.rs"), oid("a")));
+        record(
+            &mut run,
+            dir,
+            &[("a.rs", "a"), ("b.rs", "b"), ("sub/c.rs", "c")],
+        );
+        let summary = run.finish().unwrap();
+        assert_eq!(summary.added, 3);
+
+        let mut run = IncrementalRun::start(dir, "hash".to_string(), "one".to_string(), true);
+        assert!(run.keep(Path::new("a.rs"), oid("a")));
+        assert!(!run.keep(Path::new("b.rs"), oid("b2")));
+        record(&mut run, dir, &[("b.rs", "b2")]);
+        let summary = run.finish().unwrap();
+
+        assert_eq!(
+            summary,
+            RunSummary {
+                added: 0,
+                changed: 1,
+                removed: 1,
+                unchanged: 1,
+            }
+        );
+        assert!(dir.join("a.rs.md").is_file());
+        assert!(!dir.join("sub").exists());
+        let manifest = Manifest::load(dir).unwrap();
+        assert_eq!(manifest.files["b.rs"].oid, oid("b2").to_string());
+        assert_eq!(manifest.files["b.rs"].outputs, [PathBuf::from("b.rs.md")]);
+    }
+
+    #[test]
+    fn test_new_commit_regenerates_only_changed_files() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        let source = |commit: &str| SourceMetadata {
+            name: "repo".to_string(),
+            remote_url: Some("https://github.com/org/repo".to_string()),
+            snapshot: Some(Snapshot {
+                commit: oid(commit),
+                label: "main".to_string(),
+            }),
+            contributors: HashMap::new(),
+            latest_release: "v1".to_string(),
+            release_date: "2024-01-01".to_string(),
+        };
+        let settings = settings_hash(
+            "dataset",
+            &Settings::default(),
+            &LanguageDetector::default(),
+        );
+        let files = ["a.rs", "b.rs", "c.rs", "d.rs", "e.rs"];
+
+        let mut run =
+            IncrementalRun::start(dir, settings.clone(), snapshot_hash(&source("one")), true);
+        record(&mut run, dir, &files.map(|file| (file, file)));
+        run.finish().unwrap();
+
+        // The same commit leaves every header as it is.
+        let mut run =
+            IncrementalRun::start(dir, settings.clone(), snapshot_hash(&source("one")), true);
+        assert!(files
+            .iter()
+            .all(|file| run.keep(Path::new(file), oid(file))));
+        assert!(run.stale_outputs().is_empty());
+
+        // A new commit changing three files regenerates those and restamps the other two.
+        let mut run = IncrementalRun::start(dir, settings, snapshot_hash(&source("two")), true);
+        let changed = ["b.rs", "c.rs", "e.rs"];
+        for file in files {
+            let content = if changed.contains(&file) { "new" } else { file };
+            assert_eq!(
+                run.keep(Path::new(file), oid(content)),
+                !changed.contains(&file)
+            );
+        }
+        assert_eq!(
+            run.stale_outputs(),
+            [
+                (PathBuf::from("a.rs"), dir.join("a.rs.md")),
+                (PathBuf::from("d.rs"), dir.join("d.rs.md")),
+            ]
+        );
+        record(&mut run, dir, &changed.map(|file| (file, "new")));
+        assert_eq!(
+            run.finish().unwrap(),
+            RunSummary {
+                added: 0,
+                changed: 3,
+                removed: 0,
+                unchanged: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_editing_the_language_map_changes_the_settings_hash() {
+        let temp = TempDir::new().unwrap();
+        let settings = Settings {
+            language_map: Some(temp.path().join("langs.json")),
+            ..Default::default()
+        };
+        let hash_with = |map: &str| {
+            fs::write(temp.path().join("langs.json"), map).unwrap();
+            let detector = LanguageDetector::for_settings(&settings).unwrap();
+            settings_hash("multi", &settings, &detector)
+        };
+
+        let gob = hash_with(r#"{ "Scout DSL": [".gob"] }"#);
+        assert_eq!(gob, hash_with(r#"{ "Scout DSL": [".gob"] }"#));
+        assert_ne!(gob, hash_with(r#"{ "Scout DSL": [".gob", ".gobx"] }"#));
+    }
+
+    #[test]
+    fn test_manifest_outputs_outside_the_output_directory_are_ignored() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().join("out");
+        let outside = temp.path().join("outside.md");
+        fs::write(&outside, "keep me").unwrap();
+
+        let mut run = IncrementalRun::start(&dir, "hash".to_string(), "one".to_string(), true);
+        record(&mut run, &dir, &[("a.rs", "a"), ("b.rs", "b")]);
+        run.finish().unwrap();
+
+        // Point the entries at the file outside, once through `..` and once by absolute path.
+        let mut manifest: Manifest =
+            serde_json::from_str(&fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).unwrap())
+                .unwrap();
+        manifest.files.get_mut("a.rs").unwrap().outputs = vec![PathBuf::from("../outside.md")];
+        manifest.files.get_mut("b.rs").unwrap().outputs = vec![outside.clone()];
+        manifest.save(&dir).unwrap();
+
+        let run = IncrementalRun::start(&dir, "hash".to_string(), "two".to_string(), true);
+        assert!(run.stale_outputs().is_empty());
+        assert_eq!(run.finish().unwrap().removed, 0);
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "keep me");
+        assert!(remove_output(&dir, Path::new("../outside.md")).is_err());
+        assert!(outside.is_file());
+    }
+}
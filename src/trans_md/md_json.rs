@@ -1,100 +1,481 @@
 // trans_md/md_json.rs
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json;
-use serde_yaml;
-use std::fs::{self, read_to_string, File};
-use std::io::{self, Write};
-use std::path::Path;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct FileMetadata {
-    title: String,
-    date: String,
-    tags: Vec<String>,
-    uuid: String,
-    github_name: String,
-    github_url: String,
-    contributors: String,
-    latest_release: String,
-    release_date: String,
-    file_path: String,
-    size: i32,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Section {
-    uuid: String,
-    content: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct FileContent {
-    file_metadata: FileMetadata,
-    sections: Vec<Section>,
-}
-
-fn extract_from_md(file_content: &str) -> FileContent {
-    let parts: Vec<&str> = file_content.split("---\n").collect();
-
-    // Parse metadata
-    let metadata_str = parts[1];
-    let metadata: FileMetadata =
-        serde_yaml::from_str(metadata_str).expect("Failed to parse YAML metadata");
-
-    // Extract sections using UUID as delimiter
-    let content_parts = parts[2..].join("---\n");
-    let sections: Vec<&str> = content_parts
-        .split("\n[UUID:")
-        .filter(|&s| !s.is_empty())
-        .collect();
-
-    let mut parsed_sections = Vec::new();
-
-    for section in sections {
-        let section_parts: Vec<&str> = section.split("]\n").collect();
-        if section_parts.len() != 2 {
-            continue;
+use std::fs::{self, read_to_string};
+use std::path::{Path, PathBuf};
+
+use crate::tools::config::{FrontmatterFormat, JsonlRecords};
+use crate::tools::errors::CustomError;
+use crate::trans_md::document::{Document, FileMetadata, Section};
+use crate::trans_md::render::{DocumentWriter, JsonWriter, JsonlWriter};
+
+// Split a file into its frontmatter format, the frontmatter text and the rest, detecting the
+// format from the opening delimiter line. Errors carry the line they were found on.
+fn split_frontmatter(content: &str) -> Result<(FrontmatterFormat, &str, &str), (usize, String)> {
+    let format = FrontmatterFormat::ALL
+        .into_iter()
+        .find(|format| content.lines().next() == Some(format.delimiter()))
+        .ok_or((
+            1,
+            "expected frontmatter opened by `---`, `+++` or `;;;` on the first line".to_string(),
+        ))?;
+
+    let delimiter = format.delimiter();
+    let rest = content.split_once('\n').map_or("", |(_, rest)| rest);
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Ok((format, &rest[..offset], &rest[offset + line.len()..]));
         }
-        let uuid = format!("[UUID:{}", section_parts[0]);
-        let content = section_parts[1].to_string();
-        parsed_sections.push(Section { uuid, content });
+        offset += line.len();
     }
+    Err((
+        1,
+        format!("frontmatter opened here is never closed by `{}`", delimiter),
+    ))
+}
+
+// Fill section positions from the `[LINES:..]`, `[BYTES:..]` and `[PERMALINK:..]` tags that
+// follow the UUID, returning the remaining content.
+fn parse_section_tags<'a>(section: &mut Section, mut body: &'a str) -> &'a str {
+    while let Some((tag, rest)) = body
+        .strip_prefix('[')
+        .and_then(|tagged| tagged.split_once("]\n"))
+    {
+        let Some((key, value)) = tag.split_once(':') else {
+            break;
+        };
+        let range = || {
+            value
+                .split_once('-')
+                .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+                .unwrap_or_default()
+        };
+        match key {
+            "LINES" => (section.start_line, section.end_line) = range(),
+            "BYTES" => (section.start_byte, section.end_byte) = range(),
+            "PERMALINK" => section.permalink = value.to_string(),
+            _ => break,
+        }
+        body = rest;
+    }
+    body
+}
+
+// Take a chunk out of the code fence the generators put it in, returning the fence's info
+// string and the chunk. Content that is not fenced is returned as is.
+fn unfence(body: &str) -> (&str, &str) {
+    let fence_length = body.len() - body.trim_start_matches('`').len();
+    let unfenced = (fence_length >= 3)
+        .then(|| {
+            let (opening, rest) = body.split_once('\n')?;
+            let closing = format!("\n{}\n", &body[..fence_length]);
+            let content = rest
+                .strip_suffix(closing.as_str())
+                .or_else(|| rest.strip_suffix(closing.trim_end()))?;
+            Some((&opening[fence_length..], content))
+        })
+        .flatten();
+    unfenced.unwrap_or(("", body))
+}
+
+// Split the body of a dataset file at `[UUID:` lines that are outside code fences, so chunks
+// containing fences or UUID tags of their own stay in one section. Each section keeps its
+// `[UUID:` line, without the blank line that separates it from the next.
+fn split_sections(body: &str) -> Vec<&str> {
+    let mut starts = Vec::new();
+    let mut open_fence: Option<usize> = None;
+    let mut offset = 0;
+
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end();
+        let backticks = text.len() - text.trim_start_matches('`').len();
+        match open_fence {
+            None if text.starts_with("[UUID:") => starts.push(offset),
+            None if backticks >= 3 => open_fence = Some(backticks),
+            Some(length) if backticks >= length && backticks == text.len() => open_fence = None,
+            _ => {}
+        }
+        offset += line.len();
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(body.len());
+            let section = &body[start..end];
+            section
+                .strip_suffix('\n')
+                .filter(|s| s.ends_with('\n'))
+                .unwrap_or(section)
+        })
+        .collect()
+}
 
-    FileContent {
+// Parse the frontmatter of a generated file, returning its metadata and the body after it.
+pub(crate) fn parse_frontmatter<'a>(
+    path: &Path,
+    file_content: &'a str,
+) -> Result<(FileMetadata, &'a str), CustomError> {
+    let error = |line: usize, message: String| CustomError::FrontmatterError {
+        path: path.display().to_string(),
+        line,
+        message,
+    };
+    let (format, metadata_str, body) =
+        split_frontmatter(file_content).map_err(|(line, message)| error(line, message))?;
+
+    // Parse metadata. Its first line is the one after the opening delimiter.
+    let metadata = FileMetadata::from_frontmatter(format, metadata_str)
+        .map_err(|(line, message)| error(line.unwrap_or(1) + 1, message))?;
+    Ok((metadata, body))
+}
+
+pub(crate) fn extract_from_md(path: &Path, file_content: &str) -> Result<Document, CustomError> {
+    let (metadata, content_parts) = parse_frontmatter(path, file_content)?;
+
+    // Extract sections using UUID tags outside code fences as delimiters
+    let mut document = Document {
         file_metadata: metadata,
-        sections: parsed_sections,
+        ..Default::default()
+    };
+
+    for section in split_sections(content_parts) {
+        let Some((uuid, body)) = section
+            .strip_prefix("[UUID:")
+            .and_then(|tagged| tagged.split_once("]\n"))
+        else {
+            continue;
+        };
+        let mut parsed = Section {
+            uuid: uuid.to_string(),
+            ..Default::default()
+        };
+        let (fence_tag, content) = unfence(parse_section_tags(&mut parsed, body));
+        parsed.content = content.to_string();
+        document.fence_tag = fence_tag.to_string();
+        document.sections.push(parsed);
     }
+
+    Ok(document)
+}
+
+/// A file left out of a lenient conversion, listed in the error report.
+#[derive(Debug, Serialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    /// Line of the problem, when it could be located.
+    pub line: Option<usize>,
+    pub error: String,
 }
 
-fn process_file<P: AsRef<Path>>(path: P) -> io::Result<Option<FileContent>> {
-    let content = read_to_string(&path)?;
+/// Outcome of converting a directory of dataset markdown.
+#[derive(Debug, Default)]
+pub struct ConversionReport {
+    pub converted: usize,
+    pub skipped: Vec<SkippedFile>,
+}
+
+fn process_file(path: &Path) -> Result<Option<Document>, CustomError> {
+    let content = read_to_string(path)
+        .map_err(|e| CustomError::StrError(format!("Failed to read {}: {}", path.display(), e)))?;
     if content.is_empty() {
         Ok(None)
     } else {
-        Ok(Some(extract_from_md(&content)))
+        extract_from_md(path, &content).map(Some)
     }
 }
 
-fn traverse_directory<P: AsRef<Path>>(path: P) -> io::Result<Vec<FileContent>> {
-    let mut result = Vec::new();
-    if path.as_ref().is_dir() {
-        for entry in fs::read_dir(&path)? {
-            let entry = entry?;
-            let path = entry.path();
+// Parse every markdown file below `path`, in path order so the output is the same on every
+// platform. In lenient mode files that fail are added to `skipped` instead of aborting the
+// conversion.
+fn traverse_directory(
+    path: &Path,
+    lenient: bool,
+    skipped: &mut Vec<SkippedFile>,
+    emit: &mut dyn FnMut(Document) -> Result<(), CustomError>,
+) -> Result<(), CustomError> {
+    if path.is_dir() {
+        let mut paths = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        for path in paths {
             if path.is_dir() {
-                result.extend(traverse_directory(&path)?);
+                traverse_directory(&path, lenient, skipped, emit)?;
             } else if path.extension() == Some(std::ffi::OsStr::new("md")) {
-                if let Some(file_content) = process_file(&path)? {
-                    result.push(file_content);
+                match process_file(&path) {
+                    Ok(Some(file_content)) => emit(file_content)?,
+                    Ok(None) => {}
+                    Err(CustomError::FrontmatterError { line, message, .. }) if lenient => skipped
+                        .push(SkippedFile {
+                            path,
+                            line: Some(line),
+                            error: message,
+                        }),
+                    Err(e) if lenient => skipped.push(SkippedFile {
+                        path,
+                        line: None,
+                        error: e.to_string(),
+                    }),
+                    Err(e) => return Err(e),
                 }
             }
         }
     }
-    Ok(result)
+    Ok(())
+}
+
+// Error report written next to `dest_file` when a lenient conversion skipped files.
+pub fn error_report_path(dest_file: &Path) -> PathBuf {
+    dest_file.with_extension("errors.json")
+}
+
+// Convert a directory of dataset markdown into one JSON file. In lenient mode files that fail to
+// parse are skipped and listed in an error report next to the output instead.
+pub fn convert_md_to_json(
+    src_dir: &Path,
+    dest_file: &Path,
+    lenient: bool,
+) -> Result<ConversionReport, CustomError> {
+    convert_md(src_dir, dest_file, lenient, |path| {
+        Ok(Box::new(JsonWriter::create(path)?))
+    })
 }
 
-pub fn convert_md_to_json<P: AsRef<Path>>(src_dir: P, dest_file: P) -> io::Result<()> {
-    let json_output = serde_json::to_string_pretty(&traverse_directory(&src_dir)?)?;
-    File::create(dest_file)?.write_all(json_output.as_bytes())?;
+// Convert a directory of dataset markdown into JSON Lines, one record per file or per section.
+pub fn convert_md_to_jsonl(
+    src_dir: &Path,
+    dest_file: &Path,
+    records: JsonlRecords,
+    lenient: bool,
+) -> Result<ConversionReport, CustomError> {
+    convert_md(src_dir, dest_file, lenient, |path| {
+        Ok(Box::new(JsonlWriter::create(path, records)?))
+    })
+}
+
+// Stream each parsed file into a writer as soon as it is read. The output is written to a
+// temporary file next to `dest_file` and only replaces it once the conversion succeeded, so a
+// failed run leaves the previous output in place.
+fn convert_md<F>(
+    src_dir: &Path,
+    dest_file: &Path,
+    lenient: bool,
+    create_writer: F,
+) -> Result<ConversionReport, CustomError>
+where
+    F: FnOnce(&Path) -> Result<Box<dyn DocumentWriter>, CustomError>,
+{
+    let temp_file = partial_path(dest_file);
+    let converted = create_writer(&temp_file).and_then(|mut writer| {
+        let mut report = ConversionReport::default();
+        traverse_directory(src_dir, lenient, &mut report.skipped, &mut |document| {
+            report.converted += 1;
+            writer.write(&document)
+        })?;
+        writer.finish()?;
+        Ok(report)
+    });
+
+    match converted {
+        Ok(report) => {
+            fs::rename(&temp_file, dest_file)?;
+            write_error_report(dest_file, &report)?;
+            Ok(report)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp_file);
+            Err(e)
+        }
+    }
+}
+
+// Where the output is written until the conversion completes, in the same directory so the
+// rename does not cross file systems.
+fn partial_path(dest_file: &Path) -> PathBuf {
+    let file_name = dest_file
+        .file_name()
+        .map_or("output".into(), |name| name.to_string_lossy());
+    dest_file.with_file_name(format!("{}.partial", file_name))
+}
+
+// Write the report of skipped files, or remove one left by an earlier run when nothing was
+// skipped.
+fn write_error_report(dest_file: &Path, report: &ConversionReport) -> Result<(), CustomError> {
+    let report_path = error_report_path(dest_file);
+    if !report.skipped.is_empty() {
+        let errors = serde_json::to_string_pretty(&report.skipped)?;
+        fs::write(report_path, errors)?;
+    } else if report_path.exists() {
+        fs::remove_file(report_path)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trans_md::render;
+
+    #[test]
+    fn test_reads_back_every_frontmatter_format() {
+        let metadata = FileMetadata {
+            title: "repo - a.md".to_string(),
+            tags: vec!["Markdown".to_string()],
+            file_path: "docs/+++/a.md".to_string(),
+            size: 9,
+            ..Default::default()
+        };
+        let body = "\n[UUID:1234]\n[LINES:1-2]\n```\n---\n+++\n```\n";
+
+        for format in FrontmatterFormat::ALL {
+            let file = metadata.to_frontmatter(format, None).unwrap() + body;
+            let parsed = extract_from_md(Path::new("a.md"), &file).unwrap();
+            assert_eq!(parsed.file_metadata, metadata, "{:?}", format);
+            assert_eq!(parsed.sections.len(), 1);
+            assert_eq!(parsed.sections[0].end_line, 2);
+        }
+    }
+    #[test]
+    fn test_sections_split_outside_fences_only() {
+        let body = "\n[UUID:a]\n[LINES:1-4]\n````markdown\n```\n\n[UUID:fake]\n```\n````\n\n\
+                    [UUID:b]\n[LINES:5-5]\n```\nend\n```\n";
+        let file = FileMetadata::default()
+            .to_frontmatter(FrontmatterFormat::Yaml, None)
+            .unwrap()
+            + body;
+        let sections = extract_from_md(Path::new("a.md"), &file).unwrap().sections;
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].uuid, "a");
+        assert_eq!(sections[0].content, "```\n\n[UUID:fake]\n```");
+        assert_eq!(sections[1].start_line, 5);
+        assert_eq!(sections[1].content, "end");
+    }
+
+    #[test]
+    fn test_dataset_markdown_reads_back_losslessly() {
+        let document = Document {
+            file_metadata: FileMetadata {
+                title: "repo - README.md".to_string(),
+                uuid: "file".to_string(),
+                ..Default::default()
+            },
+            sections: vec![
+                Section {
+                    uuid: "one".to_string(),
+                    start_line: 1,
+                    end_line: 3,
+                    end_byte: 17,
+                    content: "# A\n```sh\nls\n".to_string(),
+                    ..Default::default()
+                },
+                Section {
+                    uuid: "two".to_string(),
+                    start_line: 4,
+                    end_line: 4,
+                    start_byte: 17,
+                    end_byte: 21,
+                    permalink: "https://example.com/README.md#L4-L4".to_string(),
+                    content: "```\n".to_string(),
+                },
+            ],
+            fence_tag: "markdown".to_string(),
+        };
+        let markdown = render::dataset_markdown(&document, FrontmatterFormat::Yaml, None).unwrap();
+
+        assert_eq!(
+            extract_from_md(Path::new("a.md"), &markdown).unwrap(),
+            document
+        );
+    }
+
+    #[test]
+    fn test_frontmatter_errors_point_at_the_file_line() {
+        let path = Path::new("dataset/a.rs.md");
+        let error = |content: &str| match extract_from_md(path, content) {
+            Err(CustomError::FrontmatterError { line, message, .. }) => (line, message),
+            other => panic!("expected a frontmatter error, got {:?}", other.map(|_| ())),
+        };
+
+        assert_eq!(error("no frontmatter\n").0, 1);
+        assert!(error("---\ntitle: a\n").1.contains("never closed"));
+        assert_eq!(error("---\ntitle: a\nsize: [1\n---\n").0, 3);
+        assert_eq!(error("+++\ntitle = 'a'\n\nsize = 'x'\n+++\n").0, 4);
+        assert_eq!(error(";;;\n{\n  \"size\": \"x\"\n}\n;;;\n").0, 3);
+        assert_eq!(
+            extract_from_md(path, "---\ntitle: a\n---\n")
+                .unwrap()
+                .file_metadata
+                .title,
+            "a"
+        );
+    }
+
+    #[test]
+    fn test_conversion_follows_path_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("dataset");
+        for path in ["b.md", "a/c.md", "a.md", "a/b/d.md"] {
+            let metadata = FileMetadata {
+                file_path: path.to_string(),
+                ..Default::default()
+            };
+            let file = src.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(
+                &file,
+                metadata
+                    .to_frontmatter(FrontmatterFormat::Yaml, None)
+                    .unwrap(),
+            )
+            .unwrap();
+        }
+        let dest = dir.path().join("out.jsonl");
+        convert_md_to_jsonl(&src, &dest, JsonlRecords::File, false).unwrap();
+
+        let paths: Vec<String> = read_to_string(&dest)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                record["file_metadata"]["file_path"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(paths, ["a/b/d.md", "a/c.md", "a.md", "b.md"]);
+    }
+
+    #[test]
+    fn test_failed_conversion_keeps_the_previous_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("dataset");
+        fs::create_dir_all(&src).unwrap();
+        let good = FileMetadata::default()
+            .to_frontmatter(FrontmatterFormat::Yaml, None)
+            .unwrap();
+        fs::write(src.join("a.md"), &good).unwrap();
+        fs::write(src.join("b.md"), "no frontmatter\n").unwrap();
+        let dest = dir.path().join("out.json");
+        let report = error_report_path(&dest);
+
+        convert_md_to_json(&src, &dest, true).unwrap();
+        let previous = read_to_string(&dest).unwrap();
+        assert!(report.exists());
+
+        fs::write(src.join("c.md"), "---\nsize: [1\n---\n").unwrap();
+        assert!(convert_md_to_json(&src, &dest, false).is_err());
+        assert_eq!(read_to_string(&dest).unwrap(), previous);
+        assert!(!partial_path(&dest).exists());
+
+        fs::write(src.join("b.md"), &good).unwrap();
+        fs::write(src.join("c.md"), &good).unwrap();
+        let converted = convert_md_to_json(&src, &dest, true).unwrap();
+        assert_eq!(converted.converted, 3);
+        assert!(!report.exists());
+    }
+}
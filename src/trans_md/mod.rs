@@ -1,4 +0,0 @@
-// trans_md/mod.rs
-
-pub mod code_md;
-pub mod md_json;
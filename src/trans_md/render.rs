@@ -0,0 +1,271 @@
+// trans_md/render.rs
+
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::tools::config::{FrontmatterFormat, JsonlRecords};
+use crate::tools::errors::CustomError;
+use crate::trans_md::document::{Document, FileMetadata, Section};
+
+// ----------------------------
+// MARKDOWN
+// ----------------------------
+
+// Wraps content in a code fence tagged with its language. The fence is one backtick longer than
+// the longest run of backticks in the content, so fences inside the content cannot close it.
+pub fn fenced(content: &str, fence_tag: &str) -> String {
+    let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{}{}\n{}\n{}\n", fence, fence_tag, content, fence)
+}
+
+// Formats a dataset section: its UUID and position tags, then the chunk in a code fence.
+fn format_section(section: &Section, fence_tag: &str) -> String {
+    let mut formatted = format!(
+        "[UUID:{}]\n[LINES:{}-{}]\n[BYTES:{}-{}]\n",
+        section.uuid, section.start_line, section.end_line, section.start_byte, section.end_byte
+    );
+    if !section.permalink.is_empty() {
+        formatted.push_str(&format!("[PERMALINK:{}]\n", section.permalink));
+    }
+    formatted.push_str(&fenced(&section.content, fence_tag));
+    formatted
+}
+
+// A file's entry in the single markdown file: its path as a heading, then its content.
+pub fn single_markdown_entry(document: &Document) -> String {
+    format!(
+        "## File: {}\n\n{}",
+        document.file_metadata.file_path,
+        fenced(&document.content(), &document.fence_tag)
+    )
+}
+
+// A file of the multi-file output: the frontmatter, then the whole content in one fence.
+pub fn file_markdown(
+    document: &Document,
+    frontmatter: FrontmatterFormat,
+    header_fields: Option<&[String]>,
+) -> Result<String, CustomError> {
+    let header = document
+        .file_metadata
+        .to_frontmatter(frontmatter, header_fields)?;
+    Ok(format!(
+        "{}\n{}",
+        header,
+        fenced(&document.content(), &document.fence_tag)
+    ))
+}
+
+// A dataset file: the frontmatter, then each section with its tags and fenced chunk.
+pub fn dataset_markdown(
+    document: &Document,
+    frontmatter: FrontmatterFormat,
+    header_fields: Option<&[String]>,
+) -> Result<String, CustomError> {
+    let header = document
+        .file_metadata
+        .to_frontmatter(frontmatter, header_fields)?;
+    let sections: Vec<String> = document
+        .sections
+        .iter()
+        .map(|section| format_section(section, &document.fence_tag))
+        .collect();
+    Ok(format!("{}\n{}", header, sections.join("\n")))
+}
+
+// ----------------------------
+// STRUCTURED WRITERS
+// ----------------------------
+
+/// Output that documents are streamed into one at a time, in source order.
+pub trait DocumentWriter {
+    fn write(&mut self, document: &Document) -> Result<(), CustomError>;
+
+    // Complete the output and return the number of records written.
+    fn finish(self: Box<Self>) -> Result<usize, CustomError>;
+
+    fn path(&self) -> &Path;
+}
+
+pub(crate) fn create_output(path: &Path) -> Result<BufWriter<File>, CustomError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+/// Streams documents into a pretty-printed JSON array, one element per file.
+pub struct JsonWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    written: usize,
+}
+
+impl JsonWriter {
+    pub fn create(path: &Path) -> Result<Self, CustomError> {
+        Ok(JsonWriter {
+            path: path.to_path_buf(),
+            out: create_output(path)?,
+            written: 0,
+        })
+    }
+}
+
+impl DocumentWriter for JsonWriter {
+    // Elements are indented as `serde_json::to_string_pretty` would indent the whole array.
+    fn write(&mut self, document: &Document) -> Result<(), CustomError> {
+        let element = serde_json::to_string_pretty(document)?.replace('\n', "\n  ");
+        let separator = if self.written == 0 { "[\n  " } else { ",\n  " };
+        write!(self.out, "{}{}", separator, element)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<usize, CustomError> {
+        let end = if self.written == 0 { "[]" } else { "\n]" };
+        self.out.write_all(end.as_bytes())?;
+        self.out.flush()?;
+        Ok(self.written)
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A JSON Lines record for one section, with its file's metadata flattened in. `uuid` is the
+/// file's UUID, as in file records.
+#[derive(Debug, Serialize)]
+struct SectionRecord<'a> {
+    #[serde(flatten)]
+    file_metadata: &'a FileMetadata,
+    section_index: usize,
+    section_uuid: &'a str,
+    start_line: usize,
+    end_line: usize,
+    start_byte: usize,
+    end_byte: usize,
+    permalink: &'a str,
+    content: &'a str,
+}
+
+/// Streams dataset files to a JSON Lines file, one line per file or per section, so large
+/// datasets never have to be held in memory.
+pub struct JsonlWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    records: JsonlRecords,
+    written: usize,
+}
+
+impl JsonlWriter {
+    pub fn create(path: &Path, records: JsonlRecords) -> Result<Self, CustomError> {
+        Ok(JsonlWriter {
+            path: path.to_path_buf(),
+            out: create_output(path)?,
+            records,
+            written: 0,
+        })
+    }
+
+    fn write_line<T: Serialize>(&mut self, record: &T) -> Result<(), CustomError> {
+        serde_json::to_writer(&mut self.out, record)?;
+        self.out.write_all(b"\n")?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+impl DocumentWriter for JsonlWriter {
+    fn write(&mut self, document: &Document) -> Result<(), CustomError> {
+        match self.records {
+            JsonlRecords::File => self.write_line(document)?,
+            JsonlRecords::Section => {
+                for (section_index, section) in document.sections.iter().enumerate() {
+                    self.write_line(&SectionRecord {
+                        file_metadata: &document.file_metadata,
+                        section_index,
+                        section_uuid: &section.uuid,
+                        start_line: section.start_line,
+                        end_line: section.end_line,
+                        start_byte: section.start_byte,
+                        end_byte: section.end_byte,
+                        permalink: &section.permalink,
+                        content: &section.content,
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<usize, CustomError> {
+        self.out.flush()?;
+        Ok(self.written)
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fences_outgrow_backtick_runs_in_content() {
+        assert_eq!(fenced("fn a() {}", "rust"), "```rust\nfn a() {}\n```\n");
+        assert_eq!(
+            fenced("text\n```sh\nls\n```\n", "markdown"),
+            "````markdown\ntext\n```sh\nls\n```\n\n````\n"
+        );
+        assert_eq!(fenced("`````", ""), "``````\n`````\n``````\n");
+    }
+
+    #[test]
+    fn test_section_records_flatten_file_metadata() {
+        let file = Document {
+            file_metadata: FileMetadata {
+                uuid: "file".to_string(),
+                file_path: "src/a.rs".to_string(),
+                ..Default::default()
+            },
+            sections: vec![
+                Section {
+                    uuid: "one".to_string(),
+                    content: "fn a() {}\n".to_string(),
+                    ..Default::default()
+                },
+                Section {
+                    uuid: "two".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.jsonl");
+        let mut writer: Box<dyn DocumentWriter> =
+            Box::new(JsonlWriter::create(&path, JsonlRecords::Section).unwrap());
+        writer.write(&file).unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let records: Vec<serde_json::Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        for (index, (record, uuid)) in records.iter().zip(["one", "two"]).enumerate() {
+            assert_eq!(record["uuid"], "file");
+            assert_eq!(record["file_path"], "src/a.rs");
+            assert_eq!(record["section_uuid"], uuid);
+            assert_eq!(record["section_index"], index);
+        }
+        assert_eq!(records[0]["content"], "fn a() {}\n");
+    }
+}